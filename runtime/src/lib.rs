@@ -0,0 +1,240 @@
+//! Functions Kaleidoscope programs can call through `extern`, everything takes and returns
+//! doubles. Output goes to stderr so it doesn't mix with emitted IR on stdout.
+
+use std::{
+    ffi::{c_void, CStr},
+    io::{stderr, stdin, stdout, BufRead, Read, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+macro_rules! cstr {
+    ($str:expr) => {
+        unsafe { CStr::from_bytes_with_nul_unchecked(concat!($str, "\0").as_bytes()) }
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn putchard(x: f64) -> f64 {
+    let mut stderr = stderr();
+    match write!(stderr, "{}", x as u8 as char).ok() {
+        Some(()) => (),
+        None => return 1.0,
+    }
+    match stderr.flush().ok() {
+        Some(()) => (),
+        None => return 1.0,
+    }
+
+    0.0
+}
+
+#[no_mangle]
+pub extern "C" fn printd(x: f64) -> f64 {
+    let mut stderr = stderr();
+    match writeln!(stderr, "{}", x).ok() {
+        Some(()) => (),
+        None => return 1.0,
+    }
+    match stderr.flush().ok() {
+        Some(()) => (),
+        None => return 1.0,
+    }
+
+    0.0
+}
+
+/// Reads a single byte from stdin, -1 at the end of input like C's getchar
+#[no_mangle]
+pub extern "C" fn getchard() -> f64 {
+    let mut byte = [0u8];
+    match stdin().read(&mut byte) {
+        Ok(1) => byte[0] as f64,
+        _ => -1.0,
+    }
+}
+
+/// Reads a line from stdin as a number, NaN if there isn't one
+#[no_mangle]
+pub extern "C" fn readd() -> f64 {
+    let mut line = String::new();
+    match stdin().lock().read_line(&mut line) {
+        Ok(read) if read > 0 => line.trim().parse().unwrap_or(f64::NAN),
+        _ => f64::NAN,
+    }
+}
+
+// Set the first time the clock is read
+static CLOCK_START: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Seconds since the clock was first read, which only ever goes forward
+#[no_mangle]
+pub extern "C" fn clockd() -> f64 {
+    let mut start = match CLOCK_START.lock() {
+        Ok(start) => start,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    start
+        .get_or_insert_with(Instant::now)
+        .elapsed()
+        .as_secs_f64()
+}
+
+// xorshift64* state, which must never be zero
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0x2545_f491_4f6c_dd1d);
+
+/// Restarts the random number sequence, the same seed always gives the same sequence
+#[no_mangle]
+pub extern "C" fn seedd(seed: f64) -> f64 {
+    let state = seed.to_bits() ^ 0x9e37_79b9_7f4a_7c15;
+    RANDOM_STATE.store(if state == 0 { 1 } else { state }, Ordering::Relaxed);
+    0.0
+}
+
+/// A random number in [0, 1)
+#[no_mangle]
+pub extern "C" fn randd() -> f64 {
+    let mut state = RANDOM_STATE.load(Ordering::Relaxed);
+    state ^= state >> 12;
+    state ^= state << 25;
+    state ^= state >> 27;
+    RANDOM_STATE.store(state, Ordering::Relaxed);
+
+    // The top 53 bits fill a double's mantissa exactly
+    let bits = state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
+
+#[no_mangle]
+pub extern "C" fn exitd(code: f64) -> f64 {
+    // process::exit doesn't run destructors, so nothing else would flush them
+    stdout().flush().ok();
+    stderr().flush().ok();
+    std::process::exit(code as i32)
+}
+
+/// Exits with a failure unless `cond` is true the way conditions see it, so 0 and NaN fail.
+/// There are no strings to describe what went wrong with, so `code` is reported instead to tell
+/// assertions apart
+#[no_mangle]
+pub extern "C" fn assertd(cond: f64, code: f64) -> f64 {
+    if let Some(message) = assertion_failure(cond, code) {
+        eprintln!("{}", message);
+        stdout().flush().ok();
+        std::process::exit(1);
+    }
+    0.0
+}
+
+fn assertion_failure(cond: f64, code: f64) -> Option<String> {
+    if cond == 0.0 || cond.is_nan() {
+        Some(format!("Assertion {} failed, got {}", code, cond))
+    } else {
+        None
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn absd(x: f64) -> f64 {
+    x.abs()
+}
+
+#[no_mangle]
+pub extern "C" fn floord(x: f64) -> f64 {
+    x.floor()
+}
+
+#[no_mangle]
+pub extern "C" fn mind(a: f64, b: f64) -> f64 {
+    a.min(b)
+}
+
+#[no_mangle]
+pub extern "C" fn maxd(a: f64, b: f64) -> f64 {
+    a.max(b)
+}
+
+/// Runtime functions only differ in how many doubles they take
+#[derive(Clone, Copy)]
+pub enum RuntimeFnPointer {
+    Nullary(extern "C" fn() -> f64),
+    Unary(extern "C" fn(f64) -> f64),
+    Binary(extern "C" fn(f64, f64) -> f64),
+}
+
+impl RuntimeFnPointer {
+    pub fn as_ptr(self) -> *mut c_void {
+        match self {
+            RuntimeFnPointer::Nullary(func) => func as *mut c_void,
+            RuntimeFnPointer::Unary(func) => func as *mut c_void,
+            RuntimeFnPointer::Binary(func) => func as *mut c_void,
+        }
+    }
+}
+
+pub struct RuntimeFunc {
+    pub name: &'static CStr,
+    pub func_pointer: RuntimeFnPointer,
+}
+
+#[used]
+pub static RUNTIME_FNS: [RuntimeFunc; 13] = [
+    RuntimeFunc {
+        name: cstr!("putchard"),
+        func_pointer: RuntimeFnPointer::Unary(putchard),
+    },
+    RuntimeFunc {
+        name: cstr!("printd"),
+        func_pointer: RuntimeFnPointer::Unary(printd),
+    },
+    RuntimeFunc {
+        name: cstr!("getchard"),
+        func_pointer: RuntimeFnPointer::Nullary(getchard),
+    },
+    RuntimeFunc {
+        name: cstr!("readd"),
+        func_pointer: RuntimeFnPointer::Nullary(readd),
+    },
+    RuntimeFunc {
+        name: cstr!("clockd"),
+        func_pointer: RuntimeFnPointer::Nullary(clockd),
+    },
+    RuntimeFunc {
+        name: cstr!("seedd"),
+        func_pointer: RuntimeFnPointer::Unary(seedd),
+    },
+    RuntimeFunc {
+        name: cstr!("randd"),
+        func_pointer: RuntimeFnPointer::Nullary(randd),
+    },
+    RuntimeFunc {
+        name: cstr!("exitd"),
+        func_pointer: RuntimeFnPointer::Unary(exitd),
+    },
+    RuntimeFunc {
+        name: cstr!("assertd"),
+        func_pointer: RuntimeFnPointer::Binary(assertd),
+    },
+    RuntimeFunc {
+        name: cstr!("absd"),
+        func_pointer: RuntimeFnPointer::Unary(absd),
+    },
+    RuntimeFunc {
+        name: cstr!("floord"),
+        func_pointer: RuntimeFnPointer::Unary(floord),
+    },
+    RuntimeFunc {
+        name: cstr!("mind"),
+        func_pointer: RuntimeFnPointer::Binary(mind),
+    },
+    RuntimeFunc {
+        name: cstr!("maxd"),
+        func_pointer: RuntimeFnPointer::Binary(maxd),
+    },
+];
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,55 @@
+use super::*;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn test_runtime_fns_have_unique_names() {
+    let mut names: Vec<&CStr> = RUNTIME_FNS.iter().map(|func| func.name).collect();
+    names.sort();
+    names.dedup();
+
+    assert_eq!(names.len(), RUNTIME_FNS.len());
+}
+
+#[test]
+fn test_randd_is_repeatable_and_in_range() {
+    seedd(42.0);
+    let first: Vec<f64> = (0..100).map(|_| randd()).collect();
+    seedd(42.0);
+    let second: Vec<f64> = (0..100).map(|_| randd()).collect();
+
+    assert_eq!(first, second);
+    assert!(first.iter().all(|x| (0.0..1.0).contains(x)), "{:?}", first);
+}
+
+#[test]
+fn test_clockd_goes_forward() {
+    let start = clockd();
+    let end = clockd();
+
+    assert!(start >= 0.0);
+    assert!(end >= start);
+}
+
+#[test]
+fn test_math_helpers() {
+    assert_eq!(absd(-2.5), 2.5);
+    assert_eq!(floord(2.5), 2.0);
+    assert_eq!(floord(-2.5), -3.0);
+    assert_eq!(mind(1.0, 2.0), 1.0);
+    assert_eq!(maxd(1.0, 2.0), 2.0);
+    assert_eq!(assertd(1.0, 1.0), 0.0);
+}
+
+#[test]
+fn test_assertion_failure_reports_code() {
+    assert_eq!(assertion_failure(1.0, 3.0), None);
+    assert_eq!(assertion_failure(-1.0, 3.0), None);
+    assert_eq!(
+        assertion_failure(0.0, 3.0),
+        Some("Assertion 3 failed, got 0".to_string())
+    );
+    assert_eq!(
+        assertion_failure(f64::NAN, 4.0),
+        Some("Assertion 4 failed, got NaN".to_string())
+    );
+}
@@ -1,19 +1,33 @@
-#[derive(Debug, PartialEq, PartialOrd)]
+use crate::lexer::Span;
+
+#[derive(Debug, Clone)]
 pub struct Expr {
     pub kind: ExprKind,
+    pub span: Span,
+}
+
+// Expressions are compared structurally, where they came from doesn't matter
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
 }
 
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExprKind {
     Number(f64),
     Variable {
         name: String,
     },
     Binary {
-        operator: char,
+        operator: String,
         lhs: Box<Expr>,
         rhs: Box<Expr>,
     },
+    Unary {
+        operator: String,
+        operand: Box<Expr>,
+    },
     Call {
         callee: String,
         args: Vec<Expr>,
@@ -27,11 +41,39 @@ pub enum ExprKind {
         body: Box<Expr>,
     },
     If(IfVal),
+    For(ForVal),
+    Var(VarVal),
+    While(WhileVal),
+    Break,
+    Continue,
+    // Evaluates each expression in turn, to whatever the last one did
+    Block(Vec<Expr>),
 }
 
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IfVal {
-    pub(crate) if_boolish_test: Box<Expr>,
-    pub(crate) then: Box<Expr>,
-    pub(crate) elves: Box<Expr>,
+    pub if_boolish_test: Box<Expr>,
+    pub then: Box<Expr>,
+    pub elves: Box<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForVal {
+    pub var_name: String,
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    pub step: Option<Box<Expr>>,
+    pub body: Box<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarVal {
+    pub bindings: Vec<(String, Option<Box<Expr>>)>,
+    pub body: Box<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhileVal {
+    pub condition: Box<Expr>,
+    pub body: Box<Expr>,
 }
@@ -0,0 +1,141 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use inkwell::context::Context;
+
+use crate::driver::{Drive, Driver, DriverOptions, EmitKind};
+
+#[derive(clap::Args)]
+pub struct BuildOptions {
+    /// Source files making up the program, run in order
+    #[clap(required = true)]
+    pub(crate) files: Vec<PathBuf>,
+    /// Where to write the executable
+    #[clap(short, long, default_value = "a.out")]
+    pub(crate) output: PathBuf,
+    /// The runtime static library to link against, defaults to the one next to this executable
+    #[clap(long)]
+    pub(crate) runtime: Option<PathBuf>,
+    /// Program used to link the executable
+    #[clap(long, default_value = "cc")]
+    pub(crate) linker: String,
+    /// Generate DWARF debug info for compiled functions
+    #[clap(long)]
+    pub(crate) debug_info: bool,
+}
+
+// What the runtime needs from the system, from `rustc --print native-static-libs`. The runtime
+// is built for the same target as the compiler, so the compiler's target decides
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+const RUNTIME_NATIVE_LIBS: &[&str] = &[
+    "-lgcc_s",
+    "-lutil",
+    "-lrt",
+    "-lpthread",
+    "-lm",
+    "-ldl",
+    "-lc",
+];
+#[cfg(all(target_os = "linux", target_env = "musl"))]
+const RUNTIME_NATIVE_LIBS: &[&str] = &["-lc"];
+#[cfg(target_os = "macos")]
+const RUNTIME_NATIVE_LIBS: &[&str] = &["-liconv", "-lSystem", "-lc", "-lm"];
+#[cfg(target_os = "freebsd")]
+const RUNTIME_NATIVE_LIBS: &[&str] = &[
+    "-lexecinfo",
+    "-lpthread",
+    "-lgcc_s",
+    "-lc",
+    "-lm",
+    "-lrt",
+    "-lutil",
+];
+// Anything else gets the usual C and math libraries and hopes for the best
+#[cfg(not(any(
+    all(target_os = "linux", any(target_env = "gnu", target_env = "musl")),
+    target_os = "macos",
+    target_os = "freebsd"
+)))]
+const RUNTIME_NATIVE_LIBS: &[&str] = &["-lpthread", "-lm", "-lc"];
+
+/// Compiles the program to an object and links it with the runtime into an executable
+pub fn build(context: &Context, options: BuildOptions) -> Result<(), Error> {
+    let runtime = match &options.runtime {
+        Some(runtime) => runtime.clone(),
+        None => default_runtime_path()?,
+    };
+    if !runtime.exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{}: runtime library not found, build it with `cargo build -p kaleidoscope-runtime` or pass --runtime",
+                runtime.display()
+            ),
+        ));
+    }
+
+    let object = std::env::temp_dir().join(format!("kaleidoscope-{}.o", std::process::id()));
+    let mut driver = Driver::new(
+        Box::new(std::io::empty()),
+        Box::new(std::io::stdout()),
+        context,
+    )
+    .with_options(DriverOptions {
+        files: options.files.clone(),
+        debug_info: options.debug_info,
+        emit: Some(EmitKind::Obj),
+        output: Some(object.clone()),
+        executable: true,
+        ..Default::default()
+    });
+
+    for file in &options.files {
+        driver.run_file(file)?;
+    }
+    if driver.error_count() == 0 {
+        driver.emit()?;
+    }
+    if driver.error_count() > 0 {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "Unable to build {} due to previous errors",
+                options.output.display()
+            ),
+        ));
+    }
+
+    let status = link_command(&options.linker, &object, &runtime, &options.output).status();
+    std::fs::remove_file(&object).ok();
+
+    let status = status.map_err(|e| Error::new(e.kind(), format!("{}: {}", options.linker, e)))?;
+    if !status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("{} failed with {}", options.linker, status),
+        ));
+    }
+    Ok(())
+}
+
+fn link_command(linker: &str, object: &Path, runtime: &Path, output: &Path) -> Command {
+    let mut command = Command::new(linker);
+    command
+        .arg(object)
+        .arg(runtime)
+        .arg("-o")
+        .arg(output)
+        .args(RUNTIME_NATIVE_LIBS);
+    command
+}
+
+// Cargo puts the runtime next to the compiler when building the workspace
+fn default_runtime_path() -> Result<PathBuf, Error> {
+    Ok(std::env::current_exe()?.with_file_name("libkaleidoscope_runtime.a"))
+}
+
+#[cfg(test)]
+mod tests;
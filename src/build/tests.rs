@@ -0,0 +1,99 @@
+use std::ffi::OsStr;
+
+use super::*;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn test_link_command_links_object_with_runtime() {
+    let command = link_command(
+        "cc",
+        Path::new("main.o"),
+        Path::new("libkaleidoscope_runtime.a"),
+        Path::new("main"),
+    );
+
+    let args: Vec<&OsStr> = command.get_args().collect();
+    assert_eq!(command.get_program(), "cc");
+    assert_eq!(
+        args[..4],
+        ["main.o", "libkaleidoscope_runtime.a", "-o", "main"].map(OsStr::new)
+    );
+    let native_libs: Vec<&OsStr> = RUNTIME_NATIVE_LIBS.iter().map(OsStr::new).collect();
+    assert!(args.ends_with(&native_libs));
+}
+
+#[test]
+fn test_build_without_runtime() {
+    let context = Context::create();
+    let options = BuildOptions {
+        files: vec![PathBuf::from("main.kal")],
+        output: PathBuf::from("main"),
+        runtime: Some(PathBuf::from("definitely/not/a/runtime.a")),
+        linker: "cc".into(),
+        debug_info: false,
+    };
+
+    let error = build(&context, options).unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+}
+
+// `cargo build --workspace` puts the runtime next to the test binary's directory, building the
+// tests alone leaves a hashed copy in deps
+fn built_runtime() -> PathBuf {
+    let test_binary = std::env::current_exe().unwrap();
+    let deps = test_binary.parent().unwrap();
+    let next_to_deps = deps.with_file_name("libkaleidoscope_runtime.a");
+    if next_to_deps.exists() {
+        return next_to_deps;
+    }
+
+    std::fs::read_dir(deps)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("libkaleidoscope_runtime") && name.ends_with(".a")
+        })
+        .expect("runtime library not found, build it with `cargo build --workspace`")
+}
+
+// Linking needs a C compiler driver, which not every machine running the tests has
+fn linker_available(linker: &str) -> bool {
+    Command::new(linker).arg("--version").output().is_ok()
+}
+
+#[test]
+fn test_build_links_and_runs_executable() {
+    if !linker_available("cc") {
+        eprintln!("skipping test_build_links_and_runs_executable: cc wasn't found to link with");
+        return;
+    }
+
+    let dir = std::env::temp_dir().join(format!("kaleidoscope-build-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.kal");
+    let executable = dir.join("main");
+    std::fs::write(
+        &source,
+        "extern printd(x);\ndef main() printd(6 * 7) * 0 + 3;\n",
+    )
+    .unwrap();
+
+    let context = Context::create();
+    let options = BuildOptions {
+        files: vec![source],
+        output: executable.clone(),
+        runtime: Some(built_runtime()),
+        linker: "cc".into(),
+        debug_info: false,
+    };
+    let result = build(&context, options);
+    let output = result.and_then(|()| Command::new(&executable).output());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // main's result is the exit code, printd writes to stderr
+    let output = output.unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "42\n");
+}
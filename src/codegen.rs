@@ -1,20 +1,30 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
 use inkwell::module::{Linkage, Module};
 use inkwell::passes::{PassManager, PassManagerBuilder};
 use inkwell::types::BasicMetadataTypeEnum;
 use inkwell::values::{
-    AnyValue, AnyValueEnum, BasicMetadataValueEnum, BasicValue, BasicValueEnum, FloatValue,
-    FunctionValue,
+    AnyValue, AnyValueEnum, BasicMetadataValueEnum, BasicValue, FloatValue, FunctionValue,
+    PointerValue,
 };
 use inkwell::FloatPredicate;
 use inkwell::OptimizationLevel::Aggressive;
 
 use crate::ast::Expr;
 use crate::ast::ExprKind;
+use crate::ast::ForVal;
 use crate::ast::IfVal;
+use crate::ast::VarVal;
+use crate::ast::WhileVal;
+use crate::diagnostic::{code, Diagnostic};
+use crate::lexer::Span;
+
+use self::debug_info::DebugInfo;
+
+mod debug_info;
 
 pub struct CodeGen<'ctx> {
     pub context: &'ctx Context,
@@ -22,23 +32,45 @@ pub struct CodeGen<'ctx> {
     pub module: Module<'ctx>,
     pub current_function: Option<FunctionValue<'ctx>>,
     pub function_pass_manager: PassManager<FunctionValue<'ctx>>,
-    pub named_values: HashMap<String, AnyValueEnum<'ctx>>,
+    // Every variable lives in a stack slot, mem2reg promotes them back to registers where it can
+    pub named_values: HashMap<String, PointerValue<'ctx>>,
+    pub debug_info: Option<DebugInfo<'ctx>>,
+    // File name and directory debug info is generated for, each new module needs its own
+    debug_info_source: Option<(String, String)>,
+    // Argument names of everything defined or declared so far, whichever module it ended up in
+    prototypes: HashMap<String, Vec<String>>,
+    // Names in the order they were first defined or declared
+    declaration_order: Vec<String>,
+    // The latest definition of each function, so it can be generated again into another module
+    definitions: HashMap<String, (Expr, Expr)>,
+    // The functions each definition calls
+    calls: HashMap<String, HashSet<String>>,
+    // The functions called by the definition currently being generated
+    called_functions: HashSet<String>,
+    // Set while callers of a redefined function are generated again, so they don't cascade
+    regenerating: bool,
+    // Where break and continue go in each loop being generated, innermost last
+    loops: Vec<LoopBlocks<'ctx>>,
 }
 
-impl<'ctx> CodeGen<'ctx> {
-    pub fn new(context: &'ctx Context, builder: Builder<'ctx>, module: Module<'ctx>) -> Self {
-        let function_pass_manager_builder = PassManagerBuilder::create();
-        function_pass_manager_builder.set_optimization_level(Aggressive);
+struct LoopBlocks<'ctx> {
+    continue_block: BasicBlock<'ctx>,
+    break_block: BasicBlock<'ctx>,
+}
+
+// Top level expressions are wrapped in a function of this name, which nothing can call
+pub(crate) const ANONYMOUS_FUNCTION_NAME: &str = "__anon";
+const POW_INTRINSIC: &str = "llvm.pow.f64";
 
-        let function_pass_manager = PassManager::create(&module);
-        // TODO: Look through the passes available to us.. there are a lot!
-        function_pass_manager_builder.populate_function_pass_manager(&function_pass_manager);
-        function_pass_manager.add_aggressive_inst_combiner_pass();
-        function_pass_manager.add_reassociate_pass();
-        function_pass_manager.add_new_gvn_pass();
-        function_pass_manager.add_cfg_simplification_pass();
+/// Whether `function` is one of LLVM's intrinsics, which are declared wherever they're used and
+/// never defined
+pub fn is_intrinsic(function: FunctionValue<'_>) -> bool {
+    function.get_name().to_bytes().starts_with(b"llvm.")
+}
 
-        function_pass_manager.initialize();
+impl<'ctx> CodeGen<'ctx> {
+    pub fn new(context: &'ctx Context, builder: Builder<'ctx>, module: Module<'ctx>) -> Self {
+        let function_pass_manager = create_function_pass_manager(&module);
 
         CodeGen {
             builder,
@@ -47,36 +79,200 @@ impl<'ctx> CodeGen<'ctx> {
             function_pass_manager,
             named_values: HashMap::new(),
             current_function: None,
+            debug_info: None,
+            debug_info_source: None,
+            prototypes: HashMap::new(),
+            declaration_order: vec![],
+            definitions: HashMap::new(),
+            calls: HashMap::new(),
+            called_functions: HashSet::new(),
+            regenerating: false,
+            loops: vec![],
         }
     }
 
-    pub fn codegen(&mut self, expr: &Expr) -> Option<AnyValueEnum<'ctx>> {
-        match &expr.kind {
-            ExprKind::Number(num) => self.codegen_number(*num).as_any_value_enum().into(),
+    pub fn enable_debug_info(&mut self, file_name: &str, directory: &str) {
+        self.debug_info = Some(DebugInfo::new(
+            self.context,
+            &self.module,
+            file_name,
+            directory,
+        ));
+        self.debug_info_source = Some((file_name.to_owned(), directory.to_owned()));
+    }
 
-            ExprKind::Variable { ref name } => self
-                .codegen_variable(name)
-                .map(|val| val.as_any_value_enum()),
+    /// Starts generating into a fresh module and hands back the previous one. Functions from
+    /// earlier modules can still be called, they're declared in the new module as needed
+    pub fn take_module(&mut self) -> Module<'ctx> {
+        let (module, debug_info) = self.new_module();
+        let (module, debug_info) = self.replace_module(module, debug_info);
+        finish_module(&module, debug_info);
+        module
+    }
+
+    // Each module has a compile unit of its own when generating debug info
+    fn new_module(&self) -> (Module<'ctx>, Option<DebugInfo<'ctx>>) {
+        let module = self.context.create_module("Kaleidoscope");
+        let debug_info = self
+            .debug_info_source
+            .as_ref()
+            .map(|(file_name, directory)| {
+                DebugInfo::new(self.context, &module, file_name, directory)
+            });
+        (module, debug_info)
+    }
+
+    fn replace_module(
+        &mut self,
+        module: Module<'ctx>,
+        debug_info: Option<DebugInfo<'ctx>>,
+    ) -> (Module<'ctx>, Option<DebugInfo<'ctx>>) {
+        self.function_pass_manager = create_function_pass_manager(&module);
+        self.current_function = None;
+        let debug_info = std::mem::replace(&mut self.debug_info, debug_info);
+        (std::mem::replace(&mut self.module, module), debug_info)
+    }
+
+    /// Generates everything currently defined or declared into a single module, leaving the
+    /// module being generated into untouched. Top level expressions aren't included
+    pub fn program_module(&mut self) -> Result<Module<'ctx>, Diagnostic> {
+        let (program, program_debug_info) = self.new_module();
+        let (current, current_debug_info) = self.replace_module(program, program_debug_info);
+
+        self.regenerating = true;
+        let result = self.declaration_order.clone().iter().try_for_each(|name| {
+            match self.definitions.get(name).cloned() {
+                Some((prototype, body)) => self.codegen_function(&prototype, &body).map(|_| ()),
+                None => {
+                    self.declare_function(name);
+                    Ok(())
+                }
+            }
+        });
+        self.regenerating = false;
+
+        let (program, program_debug_info) = self.replace_module(current, current_debug_info);
+        result.map(|_| {
+            finish_module(&program, program_debug_info);
+            program
+        })
+    }
+
+    /// Adds a C `main` to `module` that runs the program's `def main()` and exits with its
+    /// result, so the module can be linked into an executable
+    pub fn add_entry_point(
+        &self,
+        module: &Module<'ctx>,
+    ) -> Result<FunctionValue<'ctx>, Diagnostic> {
+        let program_main = module
+            .get_function("main")
+            .filter(|function| function.count_params() == 0 && function.count_basic_blocks() > 0)
+            .ok_or_else(|| {
+                Diagnostic::error(
+                    code::MISSING_MAIN,
+                    "No main function to start the program from",
+                    Span::default(),
+                )
+                .with_note("executables start by calling `def main() ...`")
+            })?;
+        // C's main has to have the name to itself
+        program_main
+            .as_global_value()
+            .as_pointer_value()
+            .set_name("kaleidoscope_main");
+
+        let i32_type = self.context.i32_type();
+        let entry_point = module.add_function("main", i32_type.fn_type(&[], false), None);
+        let builder = self.context.create_builder();
+        builder.position_at_end(self.context.append_basic_block(entry_point, "entry"));
+
+        let result = builder
+            .build_call(program_main, &[], "result")
+            .try_as_basic_value()
+            .left()
+            .map(|val| val.into_float_value())
+            .ok_or_else(|| {
+                Diagnostic::error(
+                    code::NOT_A_NUMBER,
+                    "Expected main to return a number",
+                    Span::default(),
+                )
+            })?;
+        let exit_code = builder.build_float_to_signed_int(result, i32_type, "exit_code");
+        builder.build_return(Some(&exit_code));
+
+        Ok(entry_point)
+    }
+
+    pub fn codegen(&mut self, expr: &Expr) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        if let Some(debug_info) = &mut self.debug_info {
+            debug_info.push_location(self.context, &self.builder, expr.span.start);
+        }
+
+        let result = match &expr.kind {
+            ExprKind::Number(num) => Ok(self.codegen_number(*num).as_any_value_enum()),
+
+            ExprKind::Variable { ref name } => self.codegen_variable(name, expr.span),
 
             ExprKind::Binary { operator, lhs, rhs } => self
-                .codegen_binary(*operator, lhs, rhs)
+                .codegen_binary(operator, lhs, rhs)
+                .map(|val| val.as_any_value_enum()),
+
+            ExprKind::Unary { operator, operand } => self
+                .codegen_unary(operator, operand, expr.span)
                 .map(|val| val.as_any_value_enum()),
 
             ExprKind::Call { callee, args } => self
-                .codegen_call(callee, args)
+                .codegen_call(callee, args, expr.span)
                 .map(|val| val.as_any_value_enum()),
 
-            ExprKind::Prototype { args, name } => self
-                .codegen_prototype(args, name)
-                .as_any_value_enum()
-                .into(),
+            ExprKind::Prototype { args, name } => {
+                Ok(self.codegen_prototype(args, name).as_any_value_enum())
+            }
 
             ExprKind::Function { prototype, body } => self
                 .codegen_function(prototype, body)
                 .map(|val| val.as_any_value_enum()),
 
             ExprKind::If(if_payload) => self.codegen_if(if_payload),
+
+            ExprKind::For(for_payload) => self.codegen_for(for_payload),
+
+            ExprKind::Var(var_payload) => self.codegen_var(var_payload),
+
+            ExprKind::While(while_payload) => self.codegen_while(while_payload),
+
+            ExprKind::Break | ExprKind::Continue => self.codegen_loop_control(expr),
+
+            ExprKind::Block(expressions) => self.codegen_block(expressions),
+        };
+
+        if let Some(debug_info) = &mut self.debug_info {
+            debug_info.pop_location(self.context, &self.builder);
         }
+        result
+    }
+
+    // Codegens an expression that's used as a number, which is every expression within a function
+    fn codegen_float(&mut self, expr: &Expr) -> Result<FloatValue<'ctx>, Diagnostic> {
+        self.codegen(expr)?.try_into().map_err(|_| {
+            Diagnostic::error(
+                code::NOT_A_NUMBER,
+                "Expected expression to evaluate to a number",
+                expr.span,
+            )
+        })
+    }
+
+    // Control flow needs a function to add blocks to
+    fn enclosing_function(&self, span: Span) -> Result<FunctionValue<'ctx>, Diagnostic> {
+        self.current_function.ok_or_else(|| {
+            Diagnostic::error(
+                code::INVALID_FUNCTION,
+                "Control flow is only allowed inside a function",
+                span,
+            )
+        })
     }
 }
 
@@ -85,60 +281,264 @@ impl<'ctx> CodeGen<'ctx> {
         self.context.f64_type().const_float(num)
     }
 
-    pub fn codegen_variable(&self, name: &str) -> Option<AnyValueEnum<'ctx>> {
-        self.named_values.get(name).cloned()
+    pub fn codegen_variable(
+        &self,
+        name: &str,
+        span: Span,
+    ) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        let variable = self.named_values.get(name).ok_or_else(|| {
+            Diagnostic::error(
+                code::UNKNOWN_VARIABLE,
+                format!("Unknown variable name {}", name),
+                span,
+            )
+        })?;
+        Ok(self.builder.build_load(*variable, name).as_any_value_enum())
     }
 
-    pub fn codegen_binary(&mut self, op: char, lhs: &Expr, rhs: &Expr) -> Option<FloatValue<'ctx>> {
-        let lhs: FloatValue = self.codegen(lhs)?.try_into().ok()?;
-        let rhs: FloatValue = self.codegen(rhs)?.try_into().ok()?;
+    pub fn codegen_binary(
+        &mut self,
+        op: &str,
+        lhs: &Expr,
+        rhs: &Expr,
+    ) -> Result<FloatValue<'ctx>, Diagnostic> {
+        // Assignment doesn't evaluate its lhs, so it has to be handled before anything else
+        if op == "=" {
+            return self.codegen_assignment(lhs, rhs);
+        }
+        // Nor do the logical operators always evaluate their rhs
+        if op == "and" || op == "or" {
+            return self.codegen_short_circuit(op, lhs, rhs);
+        }
+
+        let span = lhs.span.to(rhs.span);
+        let lhs = self.codegen_float(lhs)?;
+        let rhs = self.codegen_float(rhs)?;
 
         // inkwell::values::FloatMathValue
         match op {
-            '+' => self.builder.build_float_add(lhs, rhs, "addtmp").into(),
-            '-' => self.builder.build_float_sub(lhs, rhs, "subtmp").into(),
-            '*' => self.builder.build_float_mul(lhs, rhs, "multmp").into(),
-            '<' => {
-                let cmp_as_intval =
-                    self.builder
-                        .build_float_compare(FloatPredicate::ULT, lhs, rhs, "cmptmp");
-
-                self.builder
-                    .build_unsigned_int_to_float(cmp_as_intval, self.context.f64_type(), "booltmp")
-                    .into()
+            "+" => Ok(self.builder.build_float_add(lhs, rhs, "addtmp")),
+            "-" => Ok(self.builder.build_float_sub(lhs, rhs, "subtmp")),
+            "*" => Ok(self.builder.build_float_mul(lhs, rhs, "multmp")),
+            "/" => Ok(self.builder.build_float_div(lhs, rhs, "divtmp")),
+            // Like C's fmod, the result has the sign of the lhs
+            "%" => Ok(self.builder.build_float_rem(lhs, rhs, "remtmp")),
+            "^" => {
+                let pow = self.declare_pow();
+                self.build_float_call(pow, &[lhs.into(), rhs.into()], "powtmp", span)
             }
+            "<" => Ok(self.build_comparison(FloatPredicate::OLT, lhs, rhs)),
+            ">" => Ok(self.build_comparison(FloatPredicate::OGT, lhs, rhs)),
+            "<=" => Ok(self.build_comparison(FloatPredicate::OLE, lhs, rhs)),
+            ">=" => Ok(self.build_comparison(FloatPredicate::OGE, lhs, rhs)),
+            "==" => Ok(self.build_comparison(FloatPredicate::OEQ, lhs, rhs)),
+            // Like C, NaN isn't equal to anything, itself included
+            "!=" => Ok(self.build_comparison(FloatPredicate::UNE, lhs, rhs)),
+            // Anything else should be a user-defined operator, which is just a call to binary<op>
+            _ => match self.get_callee(&format!("binary{}", op)) {
+                Some(operator_fn) => {
+                    self.build_float_call(operator_fn, &[lhs.into(), rhs.into()], "binop", span)
+                }
+                None => Err(Diagnostic::error(
+                    code::UNKNOWN_OPERATOR,
+                    format!("Unknown binary operator {}", op),
+                    span,
+                )
+                .with_note(format!(
+                    "operators can be defined with `def binary{} (lhs rhs) ...`",
+                    op
+                ))),
+            },
+        }
+    }
+
+    // LLVM lowers the intrinsic to libm's pow, or folds it away when both sides are constants
+    fn declare_pow(&self) -> FunctionValue<'ctx> {
+        self.module.get_function(POW_INTRINSIC).unwrap_or_else(|| {
+            let f64_type = self.context.f64_type();
+            let fn_type = f64_type.fn_type(&[f64_type.into(), f64_type.into()], false);
+            self.module.add_function(POW_INTRINSIC, fn_type, None)
+        })
+    }
+
+    // Comparisons evaluate to 1.0 when they hold and 0.0 otherwise
+    fn build_comparison(
+        &self,
+        predicate: FloatPredicate,
+        lhs: FloatValue<'ctx>,
+        rhs: FloatValue<'ctx>,
+    ) -> FloatValue<'ctx> {
+        let cmp_as_intval = self
+            .builder
+            .build_float_compare(predicate, lhs, rhs, "cmptmp");
+
+        self.builder
+            .build_unsigned_int_to_float(cmp_as_intval, self.context.f64_type(), "booltmp")
+    }
+
+    pub fn codegen_assignment(
+        &mut self,
+        lhs: &Expr,
+        rhs: &Expr,
+    ) -> Result<FloatValue<'ctx>, Diagnostic> {
+        let var_name = match &lhs.kind {
+            ExprKind::Variable { name } => name,
             _ => {
-                eprintln!("Unexpected operator {}", op);
-                None
+                return Err(Diagnostic::error(
+                    code::INVALID_ASSIGNMENT,
+                    "Destination of '=' must be a variable",
+                    lhs.span,
+                ))
+            }
+        };
+
+        let value = self.codegen_float(rhs)?;
+        let variable = match self.named_values.get(var_name) {
+            Some(variable) => *variable,
+            None => {
+                return Err(Diagnostic::error(
+                    code::UNKNOWN_VARIABLE,
+                    format!("Unknown variable name {}", var_name),
+                    lhs.span,
+                ))
+            }
+        };
+        self.builder.build_store(variable, value);
+
+        // Assignment evaluates to the assigned value and groups from the right, so `x = y = 1`
+        // assigns both
+        Ok(value)
+    }
+
+    pub fn codegen_unary(
+        &mut self,
+        op: &str,
+        operand: &Expr,
+        span: Span,
+    ) -> Result<FloatValue<'ctx>, Diagnostic> {
+        let operand = self.codegen_float(operand)?;
+
+        // User-defined unary operators are just calls to unary<op>
+        match self.get_callee(&format!("unary{}", op)) {
+            Some(operator_fn) => {
+                self.build_float_call(operator_fn, &[operand.into()], "unop", span)
             }
+            // Negation and not are built in, but can still be overridden with unary- or unarynot
+            None if op == "-" => Ok(self.builder.build_float_neg(operand, "negtmp")),
+            // NaN is false, like it is in conditions, so not NaN is true
+            None if op == "not" => Ok(self.build_comparison(
+                FloatPredicate::UEQ,
+                operand,
+                self.context.f64_type().const_float(0.0),
+            )),
+            None => Err(Diagnostic::error(
+                code::UNKNOWN_OPERATOR,
+                format!("Unknown unary operator {}", op),
+                span,
+            )
+            .with_note(format!(
+                "operators can be defined with `def unary{} (operand) ...`",
+                op
+            ))),
         }
     }
 
-    pub fn codegen_call(&mut self, callee: &str, args: &[Expr]) -> Option<FloatValue<'ctx>> {
-        let callee_fn = self.module.get_function(callee)?;
+    pub fn codegen_call(
+        &mut self,
+        callee: &str,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<FloatValue<'ctx>, Diagnostic> {
+        let callee_fn = self.get_callee(callee).ok_or_else(|| {
+            Diagnostic::error(
+                code::UNKNOWN_FUNCTION,
+                format!("Unknown function {}", callee),
+                span,
+            )
+        })?;
 
         let callee_params = callee_fn.get_params();
         if callee_params.len() != args.len() {
-            return None;
+            return Err(Diagnostic::error(
+                code::ARITY_MISMATCH,
+                format!("Incorrect number of arguments passed to {}", callee),
+                span,
+            )
+            .with_note(format!(
+                "{} takes {} argument(s), but {} were given",
+                callee,
+                callee_params.len(),
+                args.len()
+            )));
         }
 
-        let mut compiled_args: Vec<FloatValue> = Vec::with_capacity(args.len());
+        let mut compiled_args: Vec<BasicMetadataValueEnum> = Vec::with_capacity(args.len());
 
         for arg in args {
-            compiled_args.push(self.codegen(arg)?.try_into().ok()?);
+            compiled_args.push(self.codegen_float(arg)?.into());
         }
 
-        let compiled_args: Vec<BasicMetadataValueEnum> =
-            compiled_args.into_iter().map(|val| val.into()).collect();
+        self.build_float_call(callee_fn, compiled_args.as_slice(), "call_tmp", span)
+    }
+
+    // Looks up a function that's about to be called, remembering the call so redefining the
+    // function can find its callers
+    fn get_callee(&mut self, name: &str) -> Option<FunctionValue<'ctx>> {
+        let function = self.declare_function(name)?;
+        self.called_functions.insert(name.to_owned());
+        Some(function)
+    }
+
+    // Functions from earlier modules have to be declared in the current one before they're used
+    fn declare_function(&self, name: &str) -> Option<FunctionValue<'ctx>> {
+        self.module.get_function(name).or_else(|| {
+            self.prototypes
+                .get(name)
+                .map(|args| self.add_function(args, name))
+        })
+    }
 
+    // Every function returns a double, so calls always produce one
+    fn build_float_call(
+        &self,
+        function: FunctionValue<'ctx>,
+        args: &[BasicMetadataValueEnum<'ctx>],
+        name: &str,
+        span: Span,
+    ) -> Result<FloatValue<'ctx>, Diagnostic> {
         self.builder
-            .build_call(callee_fn, compiled_args.as_slice(), "call_tmp")
+            .build_call(function, args, name)
             .try_as_basic_value()
             .left()
             .map(|val| val.into_float_value())
+            .ok_or_else(|| {
+                Diagnostic::error(code::NOT_A_NUMBER, "Expected call to return a number", span)
+            })
     }
 
-    pub fn codegen_prototype(&self, args: &[String], name: &str) -> FunctionValue<'ctx> {
+    pub fn codegen_prototype(&mut self, args: &[String], name: &str) -> FunctionValue<'ctx> {
+        unsafe {
+            if let Some(old_fn) = self.module.get_function(name) {
+                old_fn.delete()
+            }
+        }
+        self.declare(args, name);
+        self.add_function(args, name)
+    }
+
+    // Makes `name` callable from any module generated from now on
+    fn declare(&mut self, args: &[String], name: &str) {
+        if self
+            .prototypes
+            .insert(name.to_owned(), args.to_vec())
+            .is_none()
+        {
+            self.declaration_order.push(name.to_owned());
+        }
+    }
+
+    // LLVM renames the function if `name` is already taken
+    fn add_function(&self, args: &[String], name: &str) -> FunctionValue<'ctx> {
         let param_types: Vec<BasicMetadataTypeEnum> = args
             .iter()
             .map(|_| self.context.f64_type().into())
@@ -149,11 +549,6 @@ impl<'ctx> CodeGen<'ctx> {
             .f64_type()
             .fn_type(param_types.as_slice(), false);
 
-        unsafe {
-            if let Some(old_fn) = self.module.get_function(name) {
-                old_fn.delete()
-            }
-        }
         let the_fn = self
             .module
             .add_function(name, fn_type, Linkage::External.into());
@@ -169,82 +564,209 @@ impl<'ctx> CodeGen<'ctx> {
         &mut self,
         prototype: &Expr,
         body: &Expr,
-    ) -> Option<FunctionValue<'ctx>> {
+    ) -> Result<FunctionValue<'ctx>, Diagnostic> {
         let (fn_name, args) = match &prototype.kind {
-            ExprKind::Prototype { name, args } => Some((name, args)),
-            _ => None,
-        }?;
-
-        // Not the cleanest, perse. It would be better to add a tag to the function prototype
-        if self.module.get_function(fn_name).is_some() && fn_name != "__anon" {
-            eprintln!("Unable to redefine func {}", fn_name);
-            return None;
+            ExprKind::Prototype { name, args } => (name, args),
+            _ => {
+                return Err(Diagnostic::error(
+                    code::INVALID_FUNCTION,
+                    "Expected a function prototype",
+                    prototype.span,
+                ))
+            }
+        };
+
+        let mut old_fn = self.module.get_function(fn_name);
+        let existing_arg_count = self
+            .prototypes
+            .get(fn_name)
+            .map(Vec::len)
+            .or_else(|| old_fn.map(|existing_fn| existing_fn.count_params() as usize));
+
+        if let Some(existing_arg_count) = existing_arg_count.filter(|count| *count != args.len()) {
+            let has_callers = !self.callers_of(fn_name).is_empty()
+                || old_fn.map_or(false, |existing_fn| {
+                    existing_fn
+                        .as_global_value()
+                        .as_pointer_value()
+                        .get_first_use()
+                        .is_some()
+                });
+
+            if has_callers {
+                return Err(Diagnostic::error(
+                    code::REDEFINITION,
+                    format!(
+                        "Unable to redefine func {} with a different number of arguments",
+                        fn_name
+                    ),
+                    prototype.span,
+                )
+                .with_note(format!(
+                    "{} is called with {} argument(s) elsewhere",
+                    fn_name, existing_arg_count
+                )));
+            }
+            // Nothing depends on the old signature, and keeping it around would leave recursive
+            // calls in the new body resolving to it
+            if let Some(existing_fn) = old_fn.take() {
+                unsafe { existing_fn.delete() };
+            }
         }
-        let the_fn = self.codegen_prototype(args, fn_name);
+
+        // A redefinition is generated alongside the function it replaces, which is only swapped out
+        // once the new body is known to be good
+        let the_fn = self.add_function(args, fn_name);
         let bb = self.context.append_basic_block(the_fn, "entry");
         self.builder.position_at_end(bb);
 
         self.named_values.clear();
+        self.called_functions.clear();
 
-        for param in the_fn.get_param_iter() {
-            let param_as_float: FloatValue = if param.is_float_value() {
-                Some(param.into_float_value())
-            } else {
-                None
-            }?;
-
-            let param_name = param_as_float
-                .get_name()
-                .to_str()
-                .ok()
-                .map(|s| s.to_string())?;
-
-            self.named_values
-                .insert(param_name, param.as_any_value_enum());
+        if let Some(debug_info) = &mut self.debug_info {
+            debug_info.enter_function(the_fn, fn_name, args.len(), prototype.span.start);
+            // The prologue doesn't correspond to any particular expression
+            self.builder.unset_current_debug_location();
+        }
+
+        // Spill each argument to the stack so the body is free to assign to it
+        for (arg_number, (param, arg_name)) in the_fn.get_param_iter().zip(args.iter()).enumerate()
+        {
+            let alloca = self.create_entry_block_alloca(the_fn, arg_name);
+            self.builder.build_store(alloca, param);
+            self.named_values.insert(arg_name.clone(), alloca);
+
+            if let Some(debug_info) = &self.debug_info {
+                // DWARF argument numbers start at 1
+                debug_info.declare_parameter(
+                    self.context,
+                    alloca,
+                    arg_name,
+                    arg_number as u32 + 1,
+                    prototype.span.start,
+                    bb,
+                );
+            }
         }
 
         self.current_function = Some(the_fn);
 
-        match self.codegen(body) {
-            Some(value) => {
-                if value.is_function_value() || value.is_array_value() {
-                    self.current_function = None;
-                    return None;
+        let result = self.codegen_function_body(the_fn, prototype, body);
+
+        if let Some(debug_info) = &mut self.debug_info {
+            debug_info.exit_function();
+        }
+
+        if let (Ok(new_fn), Some(old_fn)) = (&result, old_fn) {
+            // Callers, including recursive calls in the new body, move over to the new definition
+            old_fn.replace_all_uses_with(*new_fn);
+            unsafe { old_fn.delete() };
+            new_fn
+                .as_global_value()
+                .as_pointer_value()
+                .set_name(fn_name);
+        }
+
+        if result.is_ok() && fn_name != ANONYMOUS_FUNCTION_NAME {
+            // Callers of an extern need to be pointed at the definition once there is one
+            let was_declared = self.prototypes.contains_key(fn_name);
+            self.declare(args, fn_name);
+            self.definitions
+                .insert(fn_name.clone(), (prototype.clone(), body.clone()));
+            self.calls
+                .insert(fn_name.clone(), std::mem::take(&mut self.called_functions));
+
+            if was_declared && !self.regenerating {
+                self.regenerate_callers(fn_name)?;
+            }
+        }
+        result
+    }
+
+    // Definitions that call `name` directly, other than `name` itself
+    fn callers_of(&self, name: &str) -> Vec<String> {
+        self.calls
+            .iter()
+            .filter(|(caller, callees)| *caller != name && callees.contains(name))
+            .map(|(caller, _)| caller.clone())
+            .collect()
+    }
+
+    // Callers in earlier modules were compiled against an old definition or a declaration of
+    // `name`, so they're generated again into the current module along with their own callers
+    fn regenerate_callers(&mut self, name: &str) -> Result<(), Diagnostic> {
+        let mut callers: Vec<String> = vec![];
+        let mut pending = vec![name.to_owned()];
+        while let Some(callee) = pending.pop() {
+            for caller in self.callers_of(&callee) {
+                if caller != name && !callers.contains(&caller) {
+                    pending.push(caller.clone());
+                    callers.push(caller);
                 }
-                let value: BasicValueEnum = value.try_into().ok()?;
+            }
+        }
+        // Keep the generated module in the same order as the source
+        callers.sort_by_key(|caller| {
+            self.declaration_order
+                .iter()
+                .position(|declared| declared == caller)
+        });
+
+        self.regenerating = true;
+        let result = callers.iter().try_for_each(|caller| {
+            let (prototype, body) = self.definitions[caller].clone();
+            self.codegen_function(&prototype, &body).map(|_| ())
+        });
+        self.regenerating = false;
+        result
+    }
+
+    fn codegen_function_body(
+        &mut self,
+        the_fn: FunctionValue<'ctx>,
+        prototype: &Expr,
+        body: &Expr,
+    ) -> Result<FunctionValue<'ctx>, Diagnostic> {
+        match self.codegen_float(body) {
+            Ok(value) => {
                 self.builder.build_return(Some(&value));
 
-                if the_fn.verify(true) {
+                // The verifier won't accept unresolved debug info, so with it the whole module is
+                // verified once it's finished. Optimizing would leave little for a debugger to
+                // look at anyway
+                if self.debug_info.is_some() {
+                    Ok(the_fn)
+                } else if the_fn.verify(true) {
                     self.function_pass_manager.run_on(&the_fn);
-                    Some(the_fn)
+                    Ok(the_fn)
                 } else {
                     self.current_function = None;
                     unsafe { the_fn.delete() };
-                    None
+                    Err(Diagnostic::error(
+                        code::INVALID_FUNCTION,
+                        "Generated invalid code for function",
+                        prototype.span,
+                    )
+                    .with_note("this is a bug in the compiler"))
                 }
             }
-            None => {
+            Err(diagnostic) => {
                 // We may have created a function while recursing inside codegen and need to clear it, if so.
                 self.current_function = None;
                 unsafe { the_fn.delete() };
-                None
+                Err(diagnostic)
             }
         }
     }
 
-    pub fn codegen_if(&mut self, if_val: &IfVal) -> Option<AnyValueEnum<'ctx>> {
-        let cond_ir = self.codegen(&if_val.if_boolish_test)?;
+    pub fn codegen_if(&mut self, if_val: &IfVal) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        let cond_ir = self.codegen_float(&if_val.if_boolish_test)?;
 
-        if !cond_ir.get_type().is_float_type() {
-            return None;
-        }
-        let cond_ir = cond_ir.into_float_value();
-
-        let current_function = &self.current_function?;
+        let current_function = self.enclosing_function(if_val.if_boolish_test.span)?;
 
-        let then_block = self.context.append_basic_block(*current_function, "then");
-        let else_block = self.context.append_basic_block(*current_function, "else");
-        let continuation_block = self.context.append_basic_block(*current_function, "cont");
+        let then_block = self.context.append_basic_block(current_function, "then");
+        let else_block = self.context.append_basic_block(current_function, "else");
+        let continuation_block = self.context.append_basic_block(current_function, "cont");
 
         // i1 that is true if not equal to zero, and false if it is
         let comparison = self.builder.build_float_compare(
@@ -261,33 +783,330 @@ impl<'ctx> CodeGen<'ctx> {
         // Codegen `then` and br to continuation block
         self.builder.position_at_end(then_block);
 
-        let then_ir = self.codegen(&if_val.then)?;
-        if !then_ir.get_type().is_float_type() {
-            return None;
-        }
+        let then_ir = self.codegen_float(&if_val.then)?;
 
-        self.builder.position_at_end(then_block);
+        // Codegen of `then` may have moved us into a different block (e.g. a nested if or a loop),
+        // so branch from wherever we ended up and use that block for the phi.
         self.builder.build_unconditional_branch(continuation_block);
-        let then_block = self.builder.get_insert_block()?;
+        let then_block = self.insert_block(if_val.then.span)?;
 
         // Codegen `else` br to continuation block
         self.builder.position_at_end(else_block);
-        let else_ir = self.codegen(&if_val.elves)?;
-        if !else_ir.get_type().is_float_type() {
-            return None;
-        }
-        self.builder.position_at_end(else_block);
+        let else_ir = self.codegen_float(&if_val.elves)?;
         self.builder.build_unconditional_branch(continuation_block);
+        let else_block = self.insert_block(if_val.elves.span)?;
 
         // Setting up the phi node
         self.builder.position_at_end(continuation_block);
         let phi = self.builder.build_phi(self.context.f64_type(), "iftmp");
 
-        let then_ir: BasicValueEnum = then_ir.try_into().ok()?;
-        let else_ir: BasicValueEnum = else_ir.try_into().ok()?;
         phi.add_incoming(&[(&then_ir, then_block), (&else_ir, else_block)]);
 
-        Some(phi.as_any_value_enum())
+        Ok(phi.as_any_value_enum())
+    }
+
+    // Branches around the rhs when the lhs already decides the result, which is always 0.0 or 1.0
+    pub fn codegen_short_circuit(
+        &mut self,
+        op: &str,
+        lhs: &Expr,
+        rhs: &Expr,
+    ) -> Result<FloatValue<'ctx>, Diagnostic> {
+        let is_and = op == "and";
+        let current_function = self.enclosing_function(lhs.span)?;
+        let zero = self.context.f64_type().const_float(0.0);
+
+        // Conditions treat anything other than 0.0 and NaN as true
+        let lhs_ir = self.codegen_float(lhs)?;
+        let lhs_bool =
+            self.builder
+                .build_float_compare(FloatPredicate::ONE, lhs_ir, zero, "lhsbool");
+        let lhs_block = self.insert_block(lhs.span)?;
+
+        let rhs_block = self.context.append_basic_block(current_function, "rhs");
+        let continuation_block = self.context.append_basic_block(current_function, "cont");
+
+        // `and` only needs its rhs when the lhs is true, `or` only when it's false
+        if is_and {
+            self.builder
+                .build_conditional_branch(lhs_bool, rhs_block, continuation_block);
+        } else {
+            self.builder
+                .build_conditional_branch(lhs_bool, continuation_block, rhs_block);
+        }
+
+        self.builder.position_at_end(rhs_block);
+        let rhs_ir = self.codegen_float(rhs)?;
+        let rhs_bool =
+            self.builder
+                .build_float_compare(FloatPredicate::ONE, rhs_ir, zero, "rhsbool");
+        self.builder.build_unconditional_branch(continuation_block);
+        let rhs_block = self.insert_block(rhs.span)?;
+
+        // Skipping the rhs means the lhs decided it: false for `and`, true for `or`
+        self.builder.position_at_end(continuation_block);
+        let bool_type = self.context.bool_type();
+        let phi = self.builder.build_phi(bool_type, &format!("{}tmp", op));
+        let decided = bool_type.const_int(u64::from(!is_and), false);
+        phi.add_incoming(&[(&decided, lhs_block), (&rhs_bool, rhs_block)]);
+
+        Ok(self.builder.build_unsigned_int_to_float(
+            phi.as_basic_value().into_int_value(),
+            self.context.f64_type(),
+            "booltmp",
+        ))
+    }
+
+    pub fn codegen_for(&mut self, for_val: &ForVal) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        let current_function = self.enclosing_function(for_val.start.span)?;
+        let alloca = self.create_entry_block_alloca(current_function, &for_val.var_name);
+
+        let start_ir = self.codegen_float(&for_val.start)?;
+        self.builder.build_store(alloca, start_ir);
+
+        // Fall through from the block we're currently in to the loop header
+        let loop_block = self.context.append_basic_block(current_function, "loop");
+        self.builder.build_unconditional_branch(loop_block);
+        self.builder.position_at_end(loop_block);
+
+        // The loop variable shadows any existing variable of the same name while in the body
+        let shadowed_value = self.named_values.insert(for_val.var_name.clone(), alloca);
+
+        // continue skips the rest of the body, but still steps the variable
+        let step_block = self.context.append_basic_block(current_function, "step");
+        let after_block = self
+            .context
+            .append_basic_block(current_function, "afterloop");
+
+        // The body's value is ignored, but any error in it is not
+        self.codegen_loop_body(&for_val.body, step_block, after_block)?;
+        self.builder.build_unconditional_branch(step_block);
+        self.builder.position_at_end(step_block);
+
+        let step_ir = match &for_val.step {
+            Some(step) => self.codegen_float(step)?,
+            None => self.context.f64_type().const_float(1.0),
+        };
+
+        let end_ir = self.codegen_float(&for_val.end)?;
+
+        // Reload the variable since the body may have assigned to it
+        let current_ir = self
+            .builder
+            .build_load(alloca, &for_val.var_name)
+            .into_float_value();
+        let next_ir = self.builder.build_float_add(current_ir, step_ir, "nextvar");
+        self.builder.build_store(alloca, next_ir);
+
+        // i1 that is true if not equal to zero, and false if it is
+        let end_comparison = self.builder.build_float_compare(
+            FloatPredicate::ONE,
+            end_ir,
+            self.context.f64_type().const_float(0.0),
+            "loopcond",
+        );
+
+        self.builder
+            .build_conditional_branch(end_comparison, loop_block, after_block);
+        self.builder.position_at_end(after_block);
+
+        // Restore whatever the loop variable shadowed
+        match shadowed_value {
+            Some(value) => self.named_values.insert(for_val.var_name.clone(), value),
+            None => self.named_values.remove(&for_val.var_name),
+        };
+
+        // A for expression always evaluates to 0.0
+        Ok(self.context.f64_type().const_float(0.0).as_any_value_enum())
+    }
+
+    pub fn codegen_while(
+        &mut self,
+        while_val: &WhileVal,
+    ) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        let current_function = self.enclosing_function(while_val.condition.span)?;
+
+        let condition_block = self
+            .context
+            .append_basic_block(current_function, "whilecond");
+        let body_block = self
+            .context
+            .append_basic_block(current_function, "whilebody");
+        let after_block = self
+            .context
+            .append_basic_block(current_function, "afterwhile");
+
+        // The condition is checked before every iteration, including the first
+        self.builder.build_unconditional_branch(condition_block);
+        self.builder.position_at_end(condition_block);
+        let condition_ir = self.codegen_float(&while_val.condition)?;
+        let comparison = self.builder.build_float_compare(
+            FloatPredicate::ONE,
+            condition_ir,
+            self.context.f64_type().const_float(0.0),
+            "loopcond",
+        );
+        self.builder
+            .build_conditional_branch(comparison, body_block, after_block);
+
+        // continue goes straight back to the condition
+        self.builder.position_at_end(body_block);
+        self.codegen_loop_body(&while_val.body, condition_block, after_block)?;
+        self.builder.build_unconditional_branch(condition_block);
+
+        self.builder.position_at_end(after_block);
+
+        // Like a for expression, a while expression always evaluates to 0.0
+        Ok(self.context.f64_type().const_float(0.0).as_any_value_enum())
+    }
+
+    // Generates a loop body that break and continue inside of jump out of
+    fn codegen_loop_body(
+        &mut self,
+        body: &Expr,
+        continue_block: BasicBlock<'ctx>,
+        break_block: BasicBlock<'ctx>,
+    ) -> Result<(), Diagnostic> {
+        self.loops.push(LoopBlocks {
+            continue_block,
+            break_block,
+        });
+        let result = self.codegen(body);
+        self.loops.pop();
+        result.map(|_| ())
+    }
+
+    pub fn codegen_loop_control(&mut self, expr: &Expr) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        let (keyword, is_break) = match expr.kind {
+            ExprKind::Break => ("break", true),
+            _ => ("continue", false),
+        };
+        let current_function = self.enclosing_function(expr.span)?;
+        let target_block = match self.loops.last() {
+            Some(blocks) if is_break => blocks.break_block,
+            Some(blocks) => blocks.continue_block,
+            None => {
+                return Err(Diagnostic::error(
+                    code::OUTSIDE_LOOP,
+                    format!("'{}' outside of a loop", keyword),
+                    expr.span,
+                ))
+            }
+        };
+        self.builder.build_unconditional_branch(target_block);
+
+        // Nothing after a jump runs, but whatever contains it still needs a block to carry on in
+        let unreachable_block = self
+            .context
+            .append_basic_block(current_function, "unreachable");
+        self.builder.position_at_end(unreachable_block);
+
+        Ok(self.context.f64_type().const_float(0.0).as_any_value_enum())
+    }
+
+    pub fn codegen_block(
+        &mut self,
+        expressions: &[Expr],
+    ) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        // A block's value is its last expression's. The parser never makes an empty one, but the
+        // AST is public, so one built by hand evaluates to 0.0
+        let mut value = self.context.f64_type().const_float(0.0).as_any_value_enum();
+        for expression in expressions {
+            value = self.codegen(expression)?;
+        }
+        Ok(value)
+    }
+
+    pub fn codegen_var(&mut self, var_val: &VarVal) -> Result<AnyValueEnum<'ctx>, Diagnostic> {
+        let current_function = self.enclosing_function(var_val.body.span)?;
+        let mut shadowed_values = Vec::with_capacity(var_val.bindings.len());
+
+        for (var_name, initializer) in &var_val.bindings {
+            // Evaluate the initializer before binding the name, so `var a = a in ...` refers to
+            // the outer `a`. Variables without an initializer start out as 0.0
+            let initial_ir = match initializer {
+                Some(initializer) => self.codegen_float(initializer)?,
+                None => self.context.f64_type().const_float(0.0),
+            };
+
+            let alloca = self.create_entry_block_alloca(current_function, var_name);
+            self.builder.build_store(alloca, initial_ir);
+
+            shadowed_values.push((var_name, self.named_values.insert(var_name.clone(), alloca)));
+        }
+
+        let body_ir = self.codegen(&var_val.body)?;
+
+        // Restore everything the bindings shadowed, newest first in case a name was bound twice
+        for (var_name, shadowed_value) in shadowed_values.into_iter().rev() {
+            match shadowed_value {
+                Some(value) => self.named_values.insert(var_name.clone(), value),
+                None => self.named_values.remove(var_name),
+            };
+        }
+
+        Ok(body_ir)
+    }
+
+    // The block the builder is currently adding instructions to
+    fn insert_block(&self, span: Span) -> Result<BasicBlock<'ctx>, Diagnostic> {
+        self.builder.get_insert_block().ok_or_else(|| {
+            Diagnostic::error(
+                code::INVALID_FUNCTION,
+                "Expected to be generating code inside a block",
+                span,
+            )
+        })
+    }
+
+    // Allocas in the entry block are what mem2reg knows how to promote
+    fn create_entry_block_alloca(
+        &self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+    ) -> PointerValue<'ctx> {
+        let builder = self.context.create_builder();
+        let entry = function
+            .get_first_basic_block()
+            .expect("functions should have an entry block before allocating variables");
+
+        match entry.get_first_instruction() {
+            Some(first_instruction) => builder.position_before(&first_instruction),
+            None => builder.position_at_end(entry),
+        }
+
+        builder.build_alloca(self.context.f64_type(), name)
+    }
+}
+
+fn create_function_pass_manager<'ctx>(module: &Module<'ctx>) -> PassManager<FunctionValue<'ctx>> {
+    let function_pass_manager_builder = PassManagerBuilder::create();
+    function_pass_manager_builder.set_optimization_level(Aggressive);
+
+    let function_pass_manager = PassManager::create(module);
+    // TODO: Look through the passes available to us.. there are a lot!
+    function_pass_manager_builder.populate_function_pass_manager(&function_pass_manager);
+    function_pass_manager.add_promote_memory_to_register_pass();
+    function_pass_manager.add_aggressive_inst_combiner_pass();
+    function_pass_manager.add_reassociate_pass();
+    function_pass_manager.add_new_gvn_pass();
+    function_pass_manager.add_cfg_simplification_pass();
+
+    function_pass_manager.initialize();
+    function_pass_manager
+}
+
+// Resolves a finished module's debug info, once everything in it has been generated. Functions
+// with debug info couldn't be verified before this, so they're verified together now
+fn finish_module(module: &Module<'_>, debug_info: Option<DebugInfo<'_>>) {
+    if let Some(debug_info) = debug_info {
+        debug_info.finalize();
+        if let Err(message) = module.verify() {
+            panic!(
+                "Generated invalid code, this is a bug in the compiler: {}",
+                message.to_string()
+            );
+        }
     }
 }
 
@@ -0,0 +1,192 @@
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::debug_info::{
+    AsDIScope, DIBasicType, DICompileUnit, DIFlags, DIFlagsConstants, DIScope, DWARFEmissionKind,
+    DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::values::{FunctionValue, PointerValue};
+
+use crate::lexer::SourceLocation;
+
+// DW_ATE_float, from the DWARF spec
+const DW_ATE_FLOAT: u32 = 0x04;
+
+pub struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    compile_unit: DICompileUnit<'ctx>,
+    double_type: DIBasicType<'ctx>,
+    // The innermost scope is last, debug locations are attached to it
+    scopes: Vec<DIScope<'ctx>>,
+    // Locations of the expressions currently being generated, innermost last
+    locations: Vec<SourceLocation>,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    pub fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        file_name: &str,
+        directory: &str,
+    ) -> Self {
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(3, false),
+        );
+
+        let (builder, compile_unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            file_name,
+            directory,
+            "Kaleidoscope Compiler",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        let double_type = builder
+            .create_basic_type("double", 64, DW_ATE_FLOAT, DIFlags::PUBLIC)
+            .expect("double is a valid basic type");
+
+        DebugInfo {
+            builder,
+            compile_unit,
+            double_type,
+            scopes: vec![],
+            locations: vec![],
+        }
+    }
+
+    /// Attaches a subprogram to `function` and makes it the current scope until `exit_function`
+    pub fn enter_function(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        arg_count: usize,
+        location: SourceLocation,
+    ) {
+        let file = self.compile_unit.get_file();
+        // Everything is a double, so a function's type only depends on how many arguments it has
+        let param_types = vec![self.double_type.as_type(); arg_count];
+        let subroutine_type = self.builder.create_subroutine_type(
+            file,
+            Some(self.double_type.as_type()),
+            &param_types,
+            DIFlags::PUBLIC,
+        );
+
+        let subprogram = self.builder.create_function(
+            self.compile_unit.as_debug_info_scope(),
+            name,
+            None,
+            file,
+            location.line,
+            subroutine_type,
+            false,
+            true,
+            location.line,
+            DIFlags::PUBLIC,
+            false,
+        );
+        function.set_subprogram(subprogram);
+        self.scopes.push(subprogram.as_debug_info_scope());
+    }
+
+    pub fn exit_function(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn declare_parameter(
+        &self,
+        context: &'ctx Context,
+        storage: PointerValue<'ctx>,
+        name: &str,
+        arg_number: u32,
+        location: SourceLocation,
+        block: BasicBlock<'ctx>,
+    ) {
+        let scope = match self.scopes.last() {
+            Some(scope) => *scope,
+            None => return,
+        };
+
+        let variable = self.builder.create_parameter_variable(
+            scope,
+            name,
+            arg_number,
+            self.compile_unit.get_file(),
+            location.line,
+            self.double_type.as_type(),
+            true,
+            DIFlags::ZERO,
+        );
+        let debug_location = self.builder.create_debug_location(
+            context,
+            location.line,
+            location.column,
+            scope,
+            None,
+        );
+
+        self.builder
+            .insert_declare_at_end(storage, Some(variable), None, debug_location, block);
+    }
+
+    /// Attributes instructions built from here on to `location`, until the matching `pop_location`
+    pub fn push_location(
+        &mut self,
+        context: &'ctx Context,
+        builder: &Builder<'ctx>,
+        location: SourceLocation,
+    ) {
+        self.locations.push(location);
+        self.emit_location(context, builder, location);
+    }
+
+    /// Goes back to attributing instructions to the enclosing expression
+    pub fn pop_location(&mut self, context: &'ctx Context, builder: &Builder<'ctx>) {
+        self.locations.pop();
+        match self.locations.last() {
+            Some(location) => self.emit_location(context, builder, *location),
+            None => builder.unset_current_debug_location(),
+        }
+    }
+
+    fn emit_location(
+        &self,
+        context: &'ctx Context,
+        builder: &Builder<'ctx>,
+        location: SourceLocation,
+    ) {
+        let scope = match self.scopes.last() {
+            Some(scope) => *scope,
+            // Outside of a function there's nothing to attach a location to
+            None => return builder.unset_current_debug_location(),
+        };
+
+        let debug_location = self.builder.create_debug_location(
+            context,
+            location.line,
+            location.column,
+            scope,
+            None,
+        );
+        builder.set_current_debug_location(context, debug_location);
+    }
+
+    /// Resolves outstanding debug info, which has to happen before a function using it is verified.
+    /// Only done once, after everything in the module has been generated
+    pub fn finalize(self) {
+        self.builder.finalize();
+    }
+}
@@ -1,12 +1,27 @@
 use std::ffi::CString;
 
 use crate::ast::{Expr, ExprKind};
+use crate::lexer::{SourceLocation, Span};
 
 use super::*;
 use crate::ast::ExprKind::*;
 use indoc::indoc;
 use pretty_assertions::assert_eq;
 
+// A single character span, offsets don't matter to codegen
+fn span_at(line: u32, column: u32) -> Span {
+    let start = SourceLocation {
+        offset: 0,
+        line,
+        column,
+    };
+    let end = SourceLocation {
+        column: column + 1,
+        ..start
+    };
+    Span { start, end }
+}
+
 fn make_generator(context: &Context) -> CodeGen {
     let module = context.create_module("Test");
     let builder = context.create_builder();
@@ -27,13 +42,32 @@ fn test_codegen_var() {
     let context = Context::create();
     let mut generator = make_generator(&context);
 
-    // This is kinda weird but all we want to do is verify that this method fetches the AnyValueEnum corresponding to
-    // the passed variable name.
-    let any_value = context.f64_type().const_float(30.0).as_any_value_enum();
-    generator.named_values.insert("x".to_owned(), any_value);
+    // Variables live in stack slots, so looking one up should load from its alloca
+    let function = generator.codegen_prototype(&[], "holder");
+    let entry = context.append_basic_block(function, "entry");
+    generator.builder.position_at_end(entry);
+    let alloca = generator.builder.build_alloca(context.f64_type(), "x");
+    generator.named_values.insert("x".to_owned(), alloca);
+
+    let result = generator.codegen_variable("x", Default::default()).unwrap();
+    let result_as_string = result.print_to_string().to_string();
+    assert!(
+        result_as_string.contains("load double, double* %x"),
+        "{}",
+        result_as_string
+    );
+}
+
+#[test]
+fn test_codegen_var_unknown() {
+    let context = Context::create();
+    let generator = make_generator(&context);
 
-    let result = generator.codegen_variable("x").unwrap();
-    assert_eq!(result, any_value);
+    let result = generator
+        .codegen_variable("x", Default::default())
+        .unwrap_err();
+    assert_eq!(result.code, code::UNKNOWN_VARIABLE);
+    assert_eq!(result.message, "Unknown variable name x");
 }
 
 #[test]
@@ -43,12 +77,14 @@ fn test_codegen_bin_plus() {
 
     let lhs = Expr {
         kind: ExprKind::Number(14.0),
+        span: Default::default(),
     };
     let rhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
 
-    let result = generator.codegen_binary('+', &lhs, &rhs).unwrap();
+    let result = generator.codegen_binary("+", &lhs, &rhs).unwrap();
     assert_eq!(result.get_constant().unwrap().0, 55.0);
 }
 
@@ -59,12 +95,14 @@ fn test_codegen_bin_minus() {
 
     let lhs = Expr {
         kind: ExprKind::Number(14.0),
+        span: Default::default(),
     };
     let rhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
 
-    let result = generator.codegen_binary('-', &lhs, &rhs).unwrap();
+    let result = generator.codegen_binary("-", &lhs, &rhs).unwrap();
     assert_eq!(result.get_constant().unwrap().0, -27.0);
 }
 
@@ -75,12 +113,14 @@ fn test_codegen_bin_mult() {
 
     let lhs = Expr {
         kind: ExprKind::Number(14.0),
+        span: Default::default(),
     };
     let rhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
 
-    let result = generator.codegen_binary('*', &lhs, &rhs).unwrap();
+    let result = generator.codegen_binary("*", &lhs, &rhs).unwrap();
     assert_eq!(result.get_constant().unwrap().0, 574.0);
 }
 
@@ -91,12 +131,14 @@ fn test_codegen_bin_less_than_true() {
 
     let lhs = Expr {
         kind: ExprKind::Number(14.0),
+        span: Default::default(),
     };
     let rhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
 
-    let result = generator.codegen_binary('<', &lhs, &rhs).unwrap();
+    let result = generator.codegen_binary("<", &lhs, &rhs).unwrap();
     assert_eq!(result.get_constant().unwrap().0, 1.0);
 }
 
@@ -107,15 +149,216 @@ fn test_codegen_bin_less_than_false() {
 
     let lhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
     let rhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
 
-    let result = generator.codegen_binary('<', &lhs, &rhs).unwrap();
+    let result = generator.codegen_binary("<", &lhs, &rhs).unwrap();
     assert_eq!(result.get_constant().unwrap().0, 0.0);
 }
 
+#[test]
+fn test_codegen_bin_comparisons() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    let number = |n| Expr {
+        kind: ExprKind::Number(n),
+        span: Default::default(),
+    };
+
+    let cases = [
+        (">", 2.0, 1.0, 1.0),
+        (">", 1.0, 1.0, 0.0),
+        ("<=", 1.0, 1.0, 1.0),
+        ("<=", 2.0, 1.0, 0.0),
+        (">=", 1.0, 2.0, 0.0),
+        ("==", 1.0, 1.0, 1.0),
+        ("==", f64::NAN, f64::NAN, 0.0),
+        ("!=", 1.0, 2.0, 1.0),
+        ("!=", f64::NAN, f64::NAN, 1.0),
+        ("<", 1.0, 2.0, 1.0),
+        ("<", 2.0, 2.0, 0.0),
+        // Only != holds when either side is NaN
+        ("<", f64::NAN, 1.0, 0.0),
+        ("<", 1.0, f64::NAN, 0.0),
+        (">", f64::NAN, 1.0, 0.0),
+        (">", 1.0, f64::NAN, 0.0),
+        ("<=", f64::NAN, 1.0, 0.0),
+        ("<=", 1.0, f64::NAN, 0.0),
+        (">=", f64::NAN, 1.0, 0.0),
+        (">=", 1.0, f64::NAN, 0.0),
+        ("==", f64::NAN, 1.0, 0.0),
+        ("!=", 1.0, f64::NAN, 1.0),
+    ];
+    for (op, lhs, rhs, expected) in cases {
+        let result = generator
+            .codegen_binary(op, &number(lhs), &number(rhs))
+            .unwrap();
+        assert_eq!(
+            result.get_constant().unwrap().0,
+            expected,
+            "{} {} {}",
+            lhs,
+            op,
+            rhs
+        );
+    }
+}
+
+#[test]
+fn test_codegen_bin_division_and_remainder() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    let number = |n| Expr {
+        kind: ExprKind::Number(n),
+        span: Default::default(),
+    };
+
+    let cases = [
+        ("/", 7.0, 2.0, 3.5),
+        ("%", 7.0, 2.0, 1.0),
+        ("%", -7.0, 2.0, -1.0),
+    ];
+    for (op, lhs, rhs, expected) in cases {
+        let result = generator
+            .codegen_binary(op, &number(lhs), &number(rhs))
+            .unwrap();
+        assert_eq!(result.get_constant().unwrap().0, expected);
+    }
+}
+
+#[test]
+fn test_codegen_bin_exponent_calls_pow_intrinsic() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    let variable = |name: &str| Expr {
+        kind: Variable { name: name.into() },
+        span: Default::default(),
+    };
+    let prototype = Expr {
+        kind: Prototype {
+            name: "power".into(),
+            args: vec!["x".into(), "y".into()],
+        },
+        span: Default::default(),
+    };
+    let body = Expr {
+        kind: Binary {
+            operator: "^".into(),
+            lhs: variable("x").into(),
+            rhs: variable("y").into(),
+        },
+        span: Default::default(),
+    };
+
+    let result = generator.codegen_function(&prototype, &body).unwrap();
+
+    let result_string = result.print_to_string().to_string();
+    assert!(
+        result_string.contains("call double @llvm.pow.f64(double %x, double %y)"),
+        "{}",
+        result_string
+    );
+    let module = generator.take_module();
+    assert!(is_intrinsic(module.get_function("llvm.pow.f64").unwrap()));
+}
+
+#[test]
+fn test_codegen_logical_operators_branch_around_rhs() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    generator.codegen_prototype(&["x".into()], "effect");
+    let variable = |name: &str| Expr {
+        kind: Variable { name: name.into() },
+        span: Default::default(),
+    };
+
+    let cases = [
+        ("and", "br i1 %lhsbool, label %rhs, label %cont"),
+        ("or", "br i1 %lhsbool, label %cont, label %rhs"),
+    ];
+    for (op, expected_branch) in cases {
+        let prototype = Expr {
+            kind: Prototype {
+                name: format!("test_{}", op),
+                args: vec!["x".into()],
+            },
+            span: Default::default(),
+        };
+        // x <op> effect(x), where the call must only happen on one side of the branch
+        let body = Expr {
+            kind: Binary {
+                operator: op.into(),
+                lhs: variable("x").into(),
+                rhs: Expr {
+                    kind: Call {
+                        callee: "effect".into(),
+                        args: vec![variable("x")],
+                    },
+                    span: Default::default(),
+                }
+                .into(),
+            },
+            span: Default::default(),
+        };
+
+        let result = generator.codegen_function(&prototype, &body).unwrap();
+
+        let result_string = result.print_to_string().to_string();
+        assert!(result_string.contains(expected_branch), "{}", result_string);
+        let rhs_block = &result_string[result_string.find("rhs:").unwrap()..];
+        assert!(
+            rhs_block.contains("call double @effect"),
+            "{}",
+            result_string
+        );
+    }
+}
+
+#[test]
+fn test_codegen_not() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    let number = |n| Expr {
+        kind: ExprKind::Number(n),
+        span: Default::default(),
+    };
+
+    let cases = [(0.0, 1.0), (2.0, 0.0), (-1.0, 0.0), (f64::NAN, 1.0)];
+    for (operand, expected) in cases {
+        let result = generator
+            .codegen_unary("not", &number(operand), Default::default())
+            .unwrap();
+        assert_eq!(
+            result.get_constant().unwrap().0,
+            expected,
+            "not {}",
+            operand
+        );
+    }
+}
+
+#[test]
+fn test_codegen_block_evaluates_to_last_expression() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    let number = |n| Expr {
+        kind: Number(n),
+        span: Default::default(),
+    };
+
+    let result = generator
+        .codegen_block(&[number(1.0), number(2.0), number(3.0)])
+        .unwrap();
+    assert_eq!(result.into_float_value().get_constant().unwrap().0, 3.0);
+
+    let result = generator.codegen_block(&[]).unwrap();
+    assert_eq!(result.into_float_value().get_constant().unwrap().0, 0.0);
+}
+
 #[test]
 fn test_codegen_bin_unknown() {
     let context = Context::create();
@@ -123,13 +366,15 @@ fn test_codegen_bin_unknown() {
 
     let lhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
     let rhs = Expr {
         kind: ExprKind::Number(41.0),
+        span: Default::default(),
     };
 
-    let result = generator.codegen_binary('#', &lhs, &rhs);
-    assert_eq!(result, None);
+    let result = generator.codegen_binary("#", &lhs, &rhs).unwrap_err();
+    assert_eq!(result.code, code::UNKNOWN_OPERATOR);
 }
 
 #[test]
@@ -143,12 +388,14 @@ fn test_codegen_call_with_gened_prototype() {
     let args = [
         Expr {
             kind: ExprKind::Number(67.0),
+            span: Default::default(),
         },
         Expr {
             kind: ExprKind::Number(67.0),
+            span: Default::default(),
         },
     ];
-    let result = generator.codegen_call(callee, &args);
+    let result = generator.codegen_call(callee, &args, Default::default());
 
     let result_as_string = result.map(|r| r.print_to_string().to_string()).unwrap();
     let expected =
@@ -166,42 +413,89 @@ fn test_codegen_call_with_gened_function() {
             name: "Juwan".into(),
             args: vec!["x".into(), "y".into()],
         },
+        span: Default::default(),
     };
     let body = Expr {
         kind: ExprKind::Binary {
-            operator: '+',
+            operator: "+".into(),
             lhs: Expr {
                 kind: ExprKind::Variable { name: "x".into() },
+                span: Default::default(),
             }
             .into(),
             rhs: Expr {
                 kind: ExprKind::Variable { name: "y".into() },
+                span: Default::default(),
             }
             .into(),
         },
+        span: Default::default(),
     };
 
-    assert!(generator.codegen_function(&prototype, &body).is_some());
+    assert!(generator.codegen_function(&prototype, &body).is_ok());
 
     let callee = "Juwan";
     let args = [
         Expr {
             kind: ExprKind::Number(67.0),
+            span: Default::default(),
         },
         Expr {
             kind: ExprKind::Number(67.0),
+            span: Default::default(),
         },
     ];
-    let result = generator.codegen_call(callee, &args);
+    let result = generator.codegen_call(callee, &args, Default::default());
     let result_as_string = result.map(|r| r.print_to_string().to_string()).unwrap();
     let expected = "%call_tmp = call double @Juwan(double 6.700000e+01, double 6.700000e+01)";
     assert_eq!(result_as_string.trim(), expected);
 }
 
+#[test]
+fn test_codegen_call_unknown_function() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    let result = generator
+        .codegen_call("nope", &[], span_at(3, 4))
+        .unwrap_err();
+
+    assert_eq!(result.code, code::UNKNOWN_FUNCTION);
+    assert_eq!(result.span, span_at(3, 4));
+}
+
+#[test]
+fn test_codegen_call_wrong_number_of_arguments() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    generator.codegen_prototype(&["x".into()], "flint");
+    let args = [
+        Expr {
+            kind: ExprKind::Number(1.0),
+            span: Default::default(),
+        },
+        Expr {
+            kind: ExprKind::Number(2.0),
+            span: Default::default(),
+        },
+    ];
+
+    let result = generator
+        .codegen_call("flint", &args, Default::default())
+        .unwrap_err();
+
+    assert_eq!(result.code, code::ARITY_MISMATCH);
+    assert_eq!(
+        result.notes,
+        vec!["flint takes 1 argument(s), but 2 were given".to_string()]
+    );
+}
+
 #[test]
 fn test_codegen_fn_prototype() {
     let context = Context::create();
-    let generator = make_generator(&context);
+    let mut generator = make_generator(&context);
 
     let args = vec!["x".into(), "y".into()];
     let name = "Moonlight";
@@ -244,23 +538,27 @@ fn test_codegen_function() {
             name: "Juwan".into(),
             args: vec!["x".into(), "y".into()],
         },
+        span: Default::default(),
     };
     let body = Expr {
         kind: ExprKind::Binary {
-            operator: '+',
+            operator: "+".into(),
             lhs: Expr {
                 kind: ExprKind::Variable { name: "x".into() },
+                span: Default::default(),
             }
             .into(),
             rhs: Expr {
                 kind: ExprKind::Variable { name: "y".into() },
+                span: Default::default(),
             }
             .into(),
         },
+        span: Default::default(),
     };
 
     let result = generator.codegen_function(&prototype, &body);
-    assert!(result.is_some());
+    assert!(result.is_ok());
     let result = result.unwrap();
     let expected = indoc! {"
     define double @Juwan(double %x, double %y) {
@@ -284,19 +582,23 @@ fn test_codegen_function_two_calls() {
             name: "Juwan".into(),
             args: vec!["x".into()],
         },
+        span: Default::default(),
     };
     let juwan_body = Expr {
         kind: ExprKind::Binary {
-            operator: '*',
+            operator: "*".into(),
             lhs: Expr {
                 kind: ExprKind::Variable { name: "x".into() },
+                span: Default::default(),
             }
             .into(),
             rhs: Expr {
                 kind: ExprKind::Number(2.0),
+                span: Default::default(),
             }
             .into(),
         },
+        span: Default::default(),
     };
 
     let howard_proto = Expr {
@@ -304,43 +606,50 @@ fn test_codegen_function_two_calls() {
             name: "Howard".into(),
             args: vec!["y".into()],
         },
+        span: Default::default(),
     };
     let howard_body = Expr {
         kind: ExprKind::Binary {
-            operator: '+',
+            operator: "+".into(),
             lhs: Expr {
                 kind: ExprKind::Variable { name: "y".into() },
+                span: Default::default(),
             }
             .into(),
             rhs: Expr {
                 kind: ExprKind::Number(4.0),
+                span: Default::default(),
             }
             .into(),
         },
+        span: Default::default(),
     };
     assert!(generator
         .codegen_function(&juwan_proto, &juwan_body)
-        .is_some());
+        .is_ok());
     assert!(generator
         .codegen_function(&howard_proto, &howard_body)
-        .is_some());
+        .is_ok());
 
     let juwan_howard_proto = Expr {
         kind: ExprKind::Prototype {
             name: "JuwanHoward".into(),
             args: vec!["x".into(), "y".into()],
         },
+        span: Default::default(),
     };
     let juwan_howard_body = Expr {
         kind: ExprKind::Binary {
-            operator: '+',
+            operator: "+".into(),
             lhs: Expr {
                 kind: ExprKind::Call {
                     callee: "Juwan".into(),
                     args: vec![Expr {
                         kind: ExprKind::Variable { name: "x".into() },
+                        span: Default::default(),
                     }],
                 },
+                span: Default::default(),
             }
             .into(),
             rhs: Expr {
@@ -348,15 +657,18 @@ fn test_codegen_function_two_calls() {
                     callee: "Howard".into(),
                     args: vec![Expr {
                         kind: ExprKind::Variable { name: "y".into() },
+                        span: Default::default(),
                     }],
                 },
+                span: Default::default(),
             }
             .into(),
         },
+        span: Default::default(),
     };
 
     let result = generator.codegen_function(&juwan_howard_proto, &juwan_howard_body);
-    assert!(result.is_some());
+    assert!(result.is_ok());
     let result = result.unwrap();
 
     // We set the name of call's to call_tmp. This test specifically tests that
@@ -388,6 +700,7 @@ fn test_codegen_if_then_else() {
                         name: "fib".into(),
                         args: vec!["x".into()],
                     },
+                    span: Default::default(),
                 }
                 .into(),
                 // Body
@@ -396,17 +709,20 @@ fn test_codegen_if_then_else() {
                     kind: ExprKind::If(IfVal {
                         if_boolish_test: Expr {
                             kind: ExprKind::Binary {
-                                operator: '<',
+                                operator: "<".into(),
                                 lhs: Expr {
                                     kind: ExprKind::Variable { name: "x".into() },
+                                    span: Default::default(),
                                 }
                                 .into(),
                                 rhs: Expr {
                                     kind: ExprKind::Number(2.0),
+                                    span: Default::default(),
                                 }
                                 .into(),
                             }
                             .into(),
+                            span: Default::default(),
                         }
                         .into(),
                         // then fib(x-1)
@@ -415,19 +731,23 @@ fn test_codegen_if_then_else() {
                                 callee: "fib".into(),
                                 args: vec![Expr {
                                     kind: ExprKind::Binary {
-                                        operator: '-',
+                                        operator: "-".into(),
                                         lhs: Expr {
                                             kind: ExprKind::Variable { name: "x".into() },
+                                            span: Default::default(),
                                         }
                                         .into(),
                                         rhs: Expr {
                                             kind: ExprKind::Number(1.0),
+                                            span: Default::default(),
                                         }
                                         .into(),
                                     },
+                                    span: Default::default(),
                                 }
                                 .into()],
                             },
+                            span: Default::default(),
                         }
                         .into(),
                         // else fib(x+1)
@@ -436,25 +756,31 @@ fn test_codegen_if_then_else() {
                                 callee: "fib".into(),
                                 args: vec![Expr {
                                     kind: ExprKind::Binary {
-                                        operator: '+',
+                                        operator: "+".into(),
                                         lhs: Expr {
                                             kind: ExprKind::Variable { name: "x".into() },
+                                            span: Default::default(),
                                         }
                                         .into(),
                                         rhs: Expr {
                                             kind: ExprKind::Number(1.0),
+                                            span: Default::default(),
                                         }
                                         .into(),
                                     },
+                                    span: Default::default(),
                                 }
                                 .into()],
                             },
+                            span: Default::default(),
                         }
                         .into(),
                     }),
+                    span: Default::default(),
                 }
                 .into(),
             },
+            span: Default::default(),
         })
         .unwrap();
 
@@ -463,7 +789,7 @@ fn test_codegen_if_then_else() {
         "
         define double @fib(double %x) {
         entry:
-          %cmptmp = fcmp ult double %x, 2.000000e+00
+          %cmptmp = fcmp olt double %x, 2.000000e+00
           %booltmp = uitofp i1 %cmptmp to double
           %comp = fcmp one double %booltmp, 0.000000e+00
           br i1 %comp, label %then, label %else
@@ -496,54 +822,79 @@ fn test_simple_codegen_reassociation_and_cse() {
             name: "test".into(),
             args: vec!["x".into()],
         },
+        span: Default::default(),
     }
     .into();
 
     let body = Expr {
         kind: Binary {
-            operator: '*',
+            operator: "*".into(),
             lhs: Expr {
                 kind: Binary {
-                    operator: '+',
+                    operator: "+".into(),
                     lhs: Expr {
                         kind: Binary {
-                            operator: '+',
-                            lhs: Expr { kind: Number(1.0) }.into(),
-                            rhs: Expr { kind: Number(2.0) }.into(),
+                            operator: "+".into(),
+                            lhs: Expr {
+                                kind: Number(1.0),
+                                span: Default::default(),
+                            }
+                            .into(),
+                            rhs: Expr {
+                                kind: Number(2.0),
+                                span: Default::default(),
+                            }
+                            .into(),
                         },
+                        span: Default::default(),
                     }
                     .into(),
                     rhs: Expr {
                         kind: Variable { name: "x".into() },
+                        span: Default::default(),
                     }
                     .into(),
                 },
+                span: Default::default(),
             }
             .into(),
             rhs: Expr {
                 kind: Binary {
-                    operator: '+',
+                    operator: "+".into(),
                     lhs: Expr {
                         kind: Variable { name: "x".into() },
+                        span: Default::default(),
                     }
                     .into(),
                     rhs: Expr {
                         kind: Binary {
-                            operator: '+',
-                            lhs: Expr { kind: Number(1.0) }.into(),
-                            rhs: Expr { kind: Number(2.0) }.into(),
+                            operator: "+".into(),
+                            lhs: Expr {
+                                kind: Number(1.0),
+                                span: Default::default(),
+                            }
+                            .into(),
+                            rhs: Expr {
+                                kind: Number(2.0),
+                                span: Default::default(),
+                            }
+                            .into(),
                         },
+                        span: Default::default(),
                     }
                     .into(),
                 },
+                span: Default::default(),
             }
             .into(),
         },
+        span: Default::default(),
     }
     .into();
 
     let function = Expr {
         kind: Function { prototype, body },
+        span: Default::default(),
     };
 
     let result = generator
@@ -563,3 +914,647 @@ fn test_simple_codegen_reassociation_and_cse() {
 
     assert_eq!(result, expected);
 }
+
+#[test]
+fn test_codegen_for_loop() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    generator.codegen_prototype(&["x".into()], "putchard");
+
+    let prototype = Expr {
+        kind: ExprKind::Prototype {
+            name: "print_stars".into(),
+            args: vec!["n".into()],
+        },
+        span: Default::default(),
+    };
+    // for i = 0, i < n in putchard(42)
+    let body = Expr {
+        kind: ExprKind::For(ForVal {
+            var_name: "i".into(),
+            start: Expr {
+                kind: Number(0.0),
+                span: Default::default(),
+            }
+            .into(),
+            end: Expr {
+                kind: Binary {
+                    operator: "<".into(),
+                    lhs: Expr {
+                        kind: Variable { name: "i".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                    rhs: Expr {
+                        kind: Variable { name: "n".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
+            }
+            .into(),
+            step: None,
+            body: Expr {
+                kind: Call {
+                    callee: "putchard".into(),
+                    args: vec![Expr {
+                        kind: Number(42.0),
+                        span: Default::default(),
+                    }],
+                },
+                span: Default::default(),
+            }
+            .into(),
+        }),
+        span: Default::default(),
+    };
+
+    let result = generator.codegen_function(&prototype, &body);
+    assert!(result.is_ok());
+
+    let result_string = result.unwrap().print_to_string().to_string();
+    assert!(result_string.contains("loop:"), "{}", result_string);
+    assert!(result_string.contains("afterloop:"), "{}", result_string);
+    assert!(
+        result_string.contains("ret double 0.000000e+00"),
+        "{}",
+        result_string
+    );
+
+    // The loop variable must not leak out of the loop
+    assert!(generator.codegen_variable("i", Default::default()).is_err());
+}
+
+#[test]
+fn test_codegen_while_loop() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    generator.codegen_prototype(&["x".into()], "effect");
+    let variable = || Expr {
+        kind: Variable { name: "x".into() },
+        span: Default::default(),
+    };
+
+    let prototype = Expr {
+        kind: Prototype {
+            name: "test".into(),
+            args: vec!["x".into()],
+        },
+        span: Default::default(),
+    };
+    // while x do x = effect(x)
+    let body = Expr {
+        kind: While(WhileVal {
+            condition: variable().into(),
+            body: Expr {
+                kind: Binary {
+                    operator: "=".into(),
+                    lhs: variable().into(),
+                    rhs: Expr {
+                        kind: Call {
+                            callee: "effect".into(),
+                            args: vec![variable()],
+                        },
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
+            }
+            .into(),
+        }),
+        span: Default::default(),
+    };
+
+    let result = generator.codegen_function(&prototype, &body).unwrap();
+
+    let result_string = result.print_to_string().to_string();
+    assert!(
+        result_string.contains("br i1 %loopcond, label %whilebody, label %afterwhile"),
+        "{}",
+        result_string
+    );
+    assert!(
+        result_string.contains("ret double 0.000000e+00"),
+        "{}",
+        result_string
+    );
+}
+
+#[test]
+fn test_codegen_loop_control_outside_loop() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    let expr = |kind| Expr {
+        kind,
+        span: Default::default(),
+    };
+    let prototype = Expr {
+        kind: Prototype {
+            name: "test".into(),
+            args: vec![],
+        },
+        span: Default::default(),
+    };
+
+    let result = generator
+        .codegen_function(&prototype, &expr(Break))
+        .unwrap_err();
+    assert_eq!(result.code, code::OUTSIDE_LOOP);
+    assert_eq!(result.message, "'break' outside of a loop");
+
+    // A loop's condition isn't inside the loop
+    let body = Expr {
+        kind: While(WhileVal {
+            condition: expr(Continue).into(),
+            body: expr(Break).into(),
+        }),
+        span: Default::default(),
+    };
+    let result = generator.codegen_function(&prototype, &body).unwrap_err();
+    assert_eq!(result.message, "'continue' outside of a loop");
+
+    // But its body is
+    let body = Expr {
+        kind: While(WhileVal {
+            condition: expr(Number(1.0)).into(),
+            body: expr(Break).into(),
+        }),
+        span: Default::default(),
+    };
+    assert!(generator.codegen_function(&prototype, &body).is_ok());
+}
+
+#[test]
+fn test_codegen_bin_user_defined() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    let prototype = Expr {
+        kind: ExprKind::Prototype {
+            name: "binary|".into(),
+            args: vec!["a".into(), "b".into()],
+        },
+        span: Default::default(),
+    };
+    let body = Expr {
+        kind: ExprKind::Binary {
+            operator: "+".into(),
+            lhs: Expr {
+                kind: ExprKind::Variable { name: "a".into() },
+                span: Default::default(),
+            }
+            .into(),
+            rhs: Expr {
+                kind: ExprKind::Variable { name: "b".into() },
+                span: Default::default(),
+            }
+            .into(),
+        },
+        span: Default::default(),
+    };
+    assert!(generator.codegen_function(&prototype, &body).is_ok());
+
+    let lhs = Expr {
+        kind: ExprKind::Number(1.0),
+        span: Default::default(),
+    };
+    let rhs = Expr {
+        kind: ExprKind::Number(2.0),
+        span: Default::default(),
+    };
+
+    let result = generator.codegen_binary("|", &lhs, &rhs);
+    let result_as_string = result.map(|r| r.print_to_string().to_string()).unwrap();
+    let expected = "%binop = call double @\"binary|\"(double 1.000000e+00, double 2.000000e+00)";
+    assert_eq!(result_as_string.trim(), expected);
+}
+
+#[test]
+fn test_codegen_unary_builtin_negation() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    let operand = Expr {
+        kind: ExprKind::Number(14.0),
+        span: Default::default(),
+    };
+
+    let result = generator
+        .codegen_unary("-", &operand, Default::default())
+        .unwrap();
+    assert_eq!(result.get_constant().unwrap().0, -14.0);
+}
+
+#[test]
+fn test_codegen_unary_user_defined() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    let prototype = Expr {
+        kind: ExprKind::Prototype {
+            name: "unary!".into(),
+            args: vec!["v".into()],
+        },
+        span: Default::default(),
+    };
+    let body = Expr {
+        kind: ExprKind::Variable { name: "v".into() },
+        span: Default::default(),
+    };
+    assert!(generator.codegen_function(&prototype, &body).is_ok());
+
+    let operand = Expr {
+        kind: ExprKind::Number(1.0),
+        span: Default::default(),
+    };
+
+    let result = generator.codegen_unary("!", &operand, Default::default());
+    let result_as_string = result.map(|r| r.print_to_string().to_string()).unwrap();
+    let expected = "%unop = call double @\"unary!\"(double 1.000000e+00)";
+    assert_eq!(result_as_string.trim(), expected);
+}
+
+#[test]
+fn test_codegen_unary_unknown() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    let operand = Expr {
+        kind: ExprKind::Number(1.0),
+        span: Default::default(),
+    };
+
+    let result = generator
+        .codegen_unary("!", &operand, Default::default())
+        .unwrap_err();
+    assert_eq!(result.code, code::UNKNOWN_OPERATOR);
+}
+
+#[test]
+fn test_codegen_var_in_and_assignment() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    let prototype = Expr {
+        kind: ExprKind::Prototype {
+            name: "triple".into(),
+            args: vec!["x".into()],
+        },
+        span: Default::default(),
+    };
+    // var y = x in y = y * 3
+    let body = Expr {
+        kind: ExprKind::Var(VarVal {
+            bindings: vec![(
+                "y".into(),
+                Some(
+                    Expr {
+                        kind: Variable { name: "x".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                ),
+            )],
+            body: Expr {
+                kind: Binary {
+                    operator: "=".into(),
+                    lhs: Expr {
+                        kind: Variable { name: "y".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                    rhs: Expr {
+                        kind: Binary {
+                            operator: "*".into(),
+                            lhs: Expr {
+                                kind: Variable { name: "y".into() },
+                                span: Default::default(),
+                            }
+                            .into(),
+                            rhs: Expr {
+                                kind: Number(3.0),
+                                span: Default::default(),
+                            }
+                            .into(),
+                        },
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
+            }
+            .into(),
+        }),
+        span: Default::default(),
+    };
+
+    let result = generator.codegen_function(&prototype, &body).unwrap();
+
+    // mem2reg should have promoted every alloca back into registers
+    let expected = indoc! {"
+        define double @triple(double %x) {
+        entry:
+          %multmp = fmul double %x, 3.000000e+00
+          ret double %multmp
+        }
+    "};
+    assert_eq!(result.print_to_string().to_string(), expected);
+
+    // The binding must not leak out of the var expression
+    assert!(generator.named_values.get("y").is_none());
+}
+
+#[test]
+fn test_codegen_assignment_to_non_variable() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+
+    let lhs = Expr {
+        kind: Number(1.0),
+        span: Default::default(),
+    };
+    let rhs = Expr {
+        kind: Number(2.0),
+        span: Default::default(),
+    };
+
+    let result = generator.codegen_binary("=", &lhs, &rhs).unwrap_err();
+    assert_eq!(result.code, code::INVALID_ASSIGNMENT);
+}
+
+#[test]
+fn test_codegen_function_with_debug_info() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    generator.enable_debug_info("test.kal", ".");
+
+    let prototype = Expr {
+        kind: ExprKind::Prototype {
+            name: "Juwan".into(),
+            args: vec!["x".into()],
+        },
+        span: span_at(3, 5),
+    };
+    let body = Expr {
+        kind: ExprKind::Binary {
+            operator: "+".into(),
+            lhs: Expr {
+                kind: ExprKind::Variable { name: "x".into() },
+                span: span_at(4, 3),
+            }
+            .into(),
+            rhs: Expr {
+                kind: ExprKind::Number(1.0),
+                span: span_at(4, 7),
+            }
+            .into(),
+        },
+        span: span_at(4, 5),
+    };
+
+    assert!(generator.codegen_function(&prototype, &body).is_ok());
+
+    let module_string = generator.module.print_to_string().to_string();
+    assert!(
+        module_string.contains("DISubprogram(name: \"Juwan\""),
+        "{}",
+        module_string
+    );
+    assert!(
+        module_string.contains("DILocalVariable(name: \"x\", arg: 1"),
+        "{}",
+        module_string
+    );
+    assert!(
+        module_string.contains("DILocation(line: 4, column: 5"),
+        "{}",
+        module_string
+    );
+}
+
+#[test]
+fn test_debug_info_has_one_compile_unit_per_module() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    generator.enable_debug_info("test.kal", ".");
+    assert!(generator
+        .codegen_function(&prototype_expr("foo"), &number_expr(1.0))
+        .is_ok());
+    generator.take_module();
+    assert!(generator
+        .codegen_function(&prototype_expr("bar"), &call_expr("foo"))
+        .is_ok());
+    generator.take_module();
+
+    // Redefining foo generates bar again alongside it
+    assert!(generator
+        .codegen_function(&prototype_expr("foo"), &number_expr(2.0))
+        .is_ok());
+    let redefinition = generator.take_module().print_to_string().to_string();
+    assert_eq!(redefinition.matches("!DICompileUnit(").count(), 1);
+    assert!(redefinition.contains("DISubprogram(name: \"foo\""));
+    assert!(redefinition.contains("DISubprogram(name: \"bar\""));
+
+    // Generating the whole program leaves the current module's compile unit as it was
+    assert!(generator
+        .codegen_function(&prototype_expr("baz"), &call_expr("bar"))
+        .is_ok());
+    for _ in 0..2 {
+        let program = generator.program_module().unwrap().print_to_string();
+        assert_eq!(program.to_string().matches("!DICompileUnit(").count(), 1);
+    }
+    let current = generator.take_module().print_to_string().to_string();
+    assert_eq!(current.matches("!DICompileUnit(").count(), 1);
+    assert_eq!(current.matches("\"Debug Info Version\"").count(), 1);
+}
+
+fn prototype_expr(name: &str) -> Expr {
+    Expr {
+        kind: ExprKind::Prototype {
+            name: name.into(),
+            args: vec![],
+        },
+        span: Default::default(),
+    }
+}
+
+fn call_expr(callee: &str) -> Expr {
+    Expr {
+        kind: ExprKind::Call {
+            callee: callee.into(),
+            args: vec![],
+        },
+        span: Default::default(),
+    }
+}
+
+fn number_expr(num: f64) -> Expr {
+    Expr {
+        kind: ExprKind::Number(num),
+        span: Default::default(),
+    }
+}
+
+#[test]
+fn test_take_module_declares_functions_from_earlier_modules() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    assert!(generator
+        .codegen_function(&prototype_expr("one"), &number_expr(1.0))
+        .is_ok());
+
+    let first = generator.take_module();
+    assert!(generator
+        .codegen_function(&prototype_expr("two"), &call_expr("one"))
+        .is_ok());
+
+    assert!(first.get_function("two").is_none());
+    let one = generator.module.get_function("one").unwrap();
+    assert_eq!(one.count_basic_blocks(), 0);
+}
+
+#[test]
+fn test_redefinition_regenerates_callers_from_earlier_modules() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    assert!(generator
+        .codegen_function(&prototype_expr("foo"), &number_expr(1.0))
+        .is_ok());
+    generator.take_module();
+    assert!(generator
+        .codegen_function(&prototype_expr("bar"), &call_expr("foo"))
+        .is_ok());
+    generator.take_module();
+    assert!(generator
+        .codegen_function(&prototype_expr("baz"), &call_expr("bar"))
+        .is_ok());
+    generator.take_module();
+
+    assert!(generator
+        .codegen_function(&prototype_expr("foo"), &number_expr(2.0))
+        .is_ok());
+
+    let defined: Vec<String> = generator
+        .module
+        .get_functions()
+        .filter(|function| function.count_basic_blocks() > 0)
+        .map(|function| function.get_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(defined, vec!["foo", "bar", "baz"]);
+}
+
+// Every function in `module`, and whether it has a body
+fn module_functions(module: &Module) -> Vec<(String, bool)> {
+    module
+        .get_functions()
+        .map(|function| {
+            let name = function.get_name().to_string_lossy().into_owned();
+            (name, function.count_basic_blocks() > 0)
+        })
+        .collect()
+}
+
+#[test]
+fn test_redefinition_cost_doesnt_scale_with_unrelated_definitions() {
+    for unrelated_count in [10, 1000] {
+        let context = Context::create();
+        let mut generator = make_generator(&context);
+        for i in 0..unrelated_count {
+            let name = format!("unrelated{}", i);
+            assert!(generator
+                .codegen_function(&prototype_expr(&name), &number_expr(i as f64))
+                .is_ok());
+            generator.take_module();
+        }
+        assert!(generator
+            .codegen_function(&prototype_expr("f"), &number_expr(1.0))
+            .is_ok());
+        generator.take_module();
+        assert!(generator
+            .codegen_function(&prototype_expr("h"), &call_expr("f"))
+            .is_ok());
+        generator.take_module();
+
+        // Only f and its caller are generated again
+        assert!(generator
+            .codegen_function(&prototype_expr("f"), &number_expr(2.0))
+            .is_ok());
+        let redefinition = generator.take_module();
+        let expected = vec![("f".to_string(), true), ("h".to_string(), true)];
+        assert_eq!(module_functions(&redefinition), expected);
+
+        // And a top level expression only needs what it calls declared
+        assert!(generator
+            .codegen_function(&prototype_expr(ANONYMOUS_FUNCTION_NAME), &call_expr("h"))
+            .is_ok());
+        let expression = generator.take_module();
+        let expected = vec![
+            (ANONYMOUS_FUNCTION_NAME.to_string(), true),
+            ("h".to_string(), false),
+        ];
+        assert_eq!(module_functions(&expression), expected);
+    }
+}
+
+#[test]
+fn test_program_module_has_latest_definitions() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    generator.codegen_prototype(&["x".into()], "sin");
+    generator.take_module();
+    assert!(generator
+        .codegen_function(&prototype_expr("foo"), &number_expr(1.0))
+        .is_ok());
+    generator.take_module();
+    assert!(generator
+        .codegen_function(&prototype_expr("foo"), &number_expr(2.0))
+        .is_ok());
+
+    let program = generator.program_module().unwrap();
+
+    let names: Vec<String> = program
+        .get_functions()
+        .map(|function| function.get_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names, vec!["sin", "foo"]);
+    let foo = program.get_function("foo").unwrap().print_to_string();
+    assert!(
+        foo.to_string().contains("ret double 2.000000e+00"),
+        "{}",
+        foo
+    );
+    // The module being generated into is left alone
+    assert!(generator.module.get_function("sin").is_none());
+}
+
+#[test]
+fn test_add_entry_point_calls_program_main() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    assert!(generator
+        .codegen_function(&prototype_expr("main"), &number_expr(3.0))
+        .is_ok());
+    let program = generator.program_module().unwrap();
+
+    assert!(generator.add_entry_point(&program).is_ok());
+
+    let ir = program.print_to_string().to_string();
+    assert!(ir.contains("define double @kaleidoscope_main()"), "{}", ir);
+    assert!(ir.contains("define i32 @main()"), "{}", ir);
+    assert!(ir.contains("call double @kaleidoscope_main()"), "{}", ir);
+    assert!(program.verify().is_ok(), "{}", ir);
+}
+
+#[test]
+fn test_add_entry_point_without_main() {
+    let context = Context::create();
+    let mut generator = make_generator(&context);
+    let program = generator.program_module().unwrap();
+
+    let result = generator.add_entry_point(&program);
+
+    assert_eq!(result.unwrap_err().code, code::MISSING_MAIN);
+}
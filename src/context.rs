@@ -0,0 +1,15 @@
+//! What the driver and sessions are created with. With the llvm feature it's LLVM's context,
+//! which everything generated lives in, without it there's nothing to keep around
+#[cfg(feature = "llvm")]
+pub use inkwell::context::Context;
+
+#[cfg(not(feature = "llvm"))]
+#[derive(Debug, Default)]
+pub struct Context;
+
+#[cfg(not(feature = "llvm"))]
+impl Context {
+    pub fn create() -> Self {
+        Context
+    }
+}
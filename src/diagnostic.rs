@@ -0,0 +1,114 @@
+use std::fmt;
+
+use crate::lexer::Span;
+
+/// Stable codes for every diagnostic we can report, parse errors are E00xx and codegen errors E01xx
+pub mod code {
+    pub const UNEXPECTED_TOKEN: &str = "E0001";
+    pub const INVALID_OPERATOR_DEFINITION: &str = "E0002";
+    pub const UNKNOWN_VARIABLE: &str = "E0101";
+    pub const UNKNOWN_FUNCTION: &str = "E0102";
+    pub const ARITY_MISMATCH: &str = "E0103";
+    pub const INVALID_ASSIGNMENT: &str = "E0104";
+    pub const UNKNOWN_OPERATOR: &str = "E0105";
+    pub const REDEFINITION: &str = "E0106";
+    pub const INVALID_FUNCTION: &str = "E0107";
+    pub const NOT_A_NUMBER: &str = "E0108";
+    pub const UNRESOLVED_EXTERN: &str = "E0109";
+    pub const MISSING_MAIN: &str = "E0110";
+    pub const OUTSIDE_LOOP: &str = "E0111";
+    pub const REQUIRES_JIT: &str = "E0112";
+    pub const SHADOWED_BUILTIN_OPERATOR: &str = "W0001";
+    pub const IGNORED_TOP_LEVEL_EXPRESSION: &str = "W0002";
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Self::error(code, message, span)
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the diagnostic rustc style, quoting the offending line of `source` and underlining
+    /// the span with carets. Spans that don't point into `source` only get the header and notes.
+    pub fn render(&self, source_name: &str, source: &str) -> String {
+        let mut rendered = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        let start = self.span.start;
+
+        // Line numbers start at 1, so a 0 line means the span was never filled in
+        let line = match start.line {
+            0 => None,
+            line => source.lines().nth(line as usize - 1),
+        };
+
+        let gutter = " ".repeat(start.line.to_string().len());
+        if let Some(line) = line {
+            // Spans covering multiple lines are underlined up to the end of their first line
+            let end_column = if self.span.end.line == start.line {
+                self.span.end.column
+            } else {
+                line.len() as u32 + 1
+            };
+            let underline_length = end_column.saturating_sub(start.column).max(1) as usize;
+
+            rendered.push_str(&format!("{}--> {}:{}\n", gutter, source_name, start));
+            rendered.push_str(&format!("{} |\n", gutter));
+            rendered.push_str(&format!("{} | {}\n", start.line, line));
+            rendered.push_str(&format!(
+                "{} | {}{}\n",
+                gutter,
+                " ".repeat(start.column.saturating_sub(1) as usize),
+                "^".repeat(underline_length)
+            ));
+        }
+
+        for note in &self.notes {
+            rendered.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,97 @@
+use super::*;
+use crate::lexer::SourceLocation;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+fn span(line: u32, start_column: u32, end_line: u32, end_column: u32) -> Span {
+    Span {
+        start: SourceLocation {
+            offset: 0,
+            line,
+            column: start_column,
+        },
+        end: SourceLocation {
+            offset: 0,
+            line: end_line,
+            column: end_column,
+        },
+    }
+}
+
+#[test]
+fn test_render_underlines_span() {
+    let diagnostic = Diagnostic::error(
+        code::UNKNOWN_VARIABLE,
+        "Unknown variable x",
+        span(2, 7, 2, 8),
+    );
+
+    let result = diagnostic.render("<stdin>", "def f(y)\n  y + x;\n");
+
+    let expected = indoc! {"
+        error[E0101]: Unknown variable x
+         --> <stdin>:2:7
+          |
+        2 |   y + x;
+          |       ^
+    "};
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_render_underlines_whole_span_and_notes() {
+    let diagnostic = Diagnostic::error(
+        code::ARITY_MISMATCH,
+        "Incorrect number of arguments passed to foo",
+        span(1, 1, 1, 10),
+    )
+    .with_note("foo takes 1 argument(s)")
+    .with_note("but 2 were given");
+
+    let result = diagnostic.render("test.kal", "foo(1, 2);");
+
+    let expected = indoc! {"
+        error[E0103]: Incorrect number of arguments passed to foo
+         --> test.kal:1:1
+          |
+        1 | foo(1, 2);
+          | ^^^^^^^^^
+          = note: foo takes 1 argument(s)
+          = note: but 2 were given
+    "};
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_render_multiline_span_stops_at_end_of_line() {
+    let diagnostic = Diagnostic::warning(
+        code::SHADOWED_BUILTIN_OPERATOR,
+        "Operator + is built in",
+        span(1, 5, 2, 4),
+    );
+
+    let result = diagnostic.render("<stdin>", "def binary+(a b)\n  a;");
+
+    let expected = indoc! {"
+        warning[W0001]: Operator + is built in
+         --> <stdin>:1:5
+          |
+        1 | def binary+(a b)
+          |     ^^^^^^^^^^^^
+    "};
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_render_without_source_location() {
+    let diagnostic = Diagnostic::error(code::INVALID_FUNCTION, "Bad function", Span::default())
+        .with_note("something went wrong");
+
+    let result = diagnostic.render("<stdin>", "");
+
+    let expected = indoc! {"
+        error[E0107]: Bad function
+          = note: something went wrong
+    "};
+    assert_eq!(result, expected);
+}
@@ -1,20 +1,37 @@
-use inkwell::{context::Context, values::AnyValue, OptimizationLevel};
-use llvm_sys::support::LLVMAddSymbol;
-use scopeguard::defer;
+#[cfg(feature = "llvm")]
+use inkwell::execution_engine::ExecutionEngine;
 
 use crate::{
     ast::{Expr, ExprKind},
-    codegen::CodeGen,
-    lexer::{Lex, Lexer, Token},
+    context::Context,
+    diagnostic::{code, Diagnostic, Severity},
+    interpreter::Interpreter,
+    lexer::{Lex, Lexer, Span, Token},
     option_ext::OptionExt,
     parser::{Parse, Parser},
 };
+#[cfg(feature = "llvm")]
+use crate::{build::BuildOptions, codegen::CodeGen, library};
 
+#[cfg(feature = "llvm")]
+use std::collections::HashMap;
 use std::{
-    ffi::c_void,
-    io::{Read, Write},
+    fs::File,
+    io::{BufReader, Cursor, Error, Read, Write},
+    path::{Path, PathBuf},
 };
 
+mod commands;
+#[cfg(feature = "llvm")]
+mod jit;
+
+// Why anything needing generated code fails with the interpreter
+#[cfg(feature = "llvm")]
+const INTERPRETER_NOTE: &str = "the interpreter runs the syntax tree and never generates any code";
+#[cfg(not(feature = "llvm"))]
+const INTERPRETER_NOTE: &str =
+    "this kaleidoscope was built without the llvm feature, so the interpreter is the only backend";
+
 pub trait Drive<'ctx> {
     fn new(input: Box<dyn Read>, output: Box<dyn Write>, context: &'ctx Context) -> Self;
     fn run(&mut self) -> Result<(), std::io::Error>;
@@ -24,30 +41,127 @@ pub trait Drive<'ctx> {
     fn with_options(self, options: DriverOptions) -> Self;
 }
 
-#[derive(clap::Parser)]
+#[derive(clap::Parser, Default)]
 pub struct DriverOptions {
+    /// Source files to run in order, input is read interactively from stdin when there are none
+    pub files: Vec<PathBuf>,
+    #[clap(long)]
+    pub print_parse: bool,
+    #[clap(long)]
+    pub print_ir: bool,
+    /// Generate DWARF debug info for compiled functions
     #[clap(long)]
-    pub(crate) print_parse: bool,
+    pub debug_info: bool,
+    /// Write the compiled module to a file once all input has been handled
+    #[clap(long, arg_enum)]
+    pub emit: Option<EmitKind>,
+    /// Where to write the emitted file, defaults to output.<kind>
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    /// Target triple to emit objects and assembly for, defaults to the host
     #[clap(long)]
-    pub(crate) print_ir: bool,
+    pub target: Option<String>,
+    /// Shared library for externs to resolve against, can be given more than once
+    #[clap(long, value_name = "LIB")]
+    pub load: Vec<PathBuf>,
+    /// What runs definitions and top level expressions
+    #[cfg_attr(feature = "llvm", clap(long, arg_enum, default_value = "jit"))]
+    #[cfg_attr(not(feature = "llvm"), clap(long, arg_enum, default_value = "interp"))]
+    pub backend: Backend,
+    #[cfg(feature = "llvm")]
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    // Nothing is JIT compiled or run when building an executable, and the emitted object gets an
+    // entry point that calls `main()`
+    #[cfg(feature = "llvm")]
+    #[clap(skip)]
+    pub(crate) executable: bool,
+}
+
+#[cfg(feature = "llvm")]
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Compile source files into a standalone executable that starts by calling `def main()`
+    Build(BuildOptions),
+}
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum EmitKind {
+    Obj,
+    Asm,
+    Bc,
+    Ll,
+}
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /// Compile with LLVM and run the machine code
+    #[cfg(feature = "llvm")]
+    Jit,
+    /// Walk the syntax tree, nothing is compiled
+    Interp,
+}
+
+impl Default for Backend {
+    #[cfg(feature = "llvm")]
+    fn default() -> Self {
+        Backend::Jit
+    }
+
+    #[cfg(not(feature = "llvm"))]
+    fn default() -> Self {
+        Backend::Interp
+    }
+}
+
+#[cfg(feature = "llvm")]
+impl EmitKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            EmitKind::Obj => "o",
+            EmitKind::Asm => "s",
+            EmitKind::Bc => "bc",
+            EmitKind::Ll => "ll",
+        }
+    }
 }
 
 pub struct Driver<'a> {
     parser: Parser,
     lexer: Lexer<Box<dyn Read>>,
-    codegen: CodeGen<'a>,
+    #[cfg_attr(not(feature = "llvm"), allow(dead_code))]
+    context: &'a Context,
+    // Only the JIT backend generates code, so this is created the first time it's needed
+    #[cfg(feature = "llvm")]
+    codegen: Option<CodeGen<'a>>,
     output: Box<dyn Write>,
+    // Diagnostics, kept apart from what top level expressions evaluate to
+    error_output: Box<dyn Write>,
     options: DriverOptions,
-}
-
-impl Driver<'_> {
-    fn shim_lib_functions() {
-        for func in &crate::library::PRINT_FNS {
-            let fn_name = func.name.as_ptr();
-            let fn_ptr = func.func_pointer as *mut c_void;
-            unsafe { LLVMAddSymbol(fn_name, fn_ptr) };
-        }
-    }
+    // What diagnostics call the input
+    source_name: String,
+    // Only interactive input gets a prompt
+    show_prompt: bool,
+    errors: Vec<Diagnostic>,
+    // What the latest top level expression in the current input evaluated to
+    last_value: Option<f64>,
+    // Top level expressions are reported instead of run while this is set
+    pub(crate) definitions_only: bool,
+    // Lives for the whole session once started, each definition and top level expression is
+    // compiled into a module of its own and added to it. The interpreter never starts it
+    #[cfg(feature = "llvm")]
+    engine: Option<ExecutionEngine<'a>>,
+    // The symbol the JIT knows the latest definition of each function by
+    #[cfg(feature = "llvm")]
+    symbols: HashMap<String, String>,
+    #[cfg(feature = "llvm")]
+    symbol_count: usize,
+    // Externs that couldn't be resolved when each symbol's module was added, including any
+    // missing from the modules it calls into. Running code that needs them would abort
+    #[cfg(feature = "llvm")]
+    missing_externs: HashMap<String, Vec<String>>,
+    // Stands in for codegen and the JIT with `--backend interp`
+    interpreter: Interpreter,
 }
 
 impl<'ctx, 'a> Drive<'ctx> for Driver<'a>
@@ -55,35 +169,58 @@ where
     'ctx: 'a,
 {
     fn with_options(self, options: DriverOptions) -> Self {
+        // Anything already generated keeps the options it was generated with
+        #[cfg(feature = "llvm")]
+        let codegen = self.codegen.map(|mut codegen| {
+            if options.debug_info {
+                jit::enable_debug_info(&mut codegen, &options, &self.source_name);
+            }
+            codegen
+        });
+
         Self {
-            parser: self.parser,
-            lexer: self.lexer,
-            codegen: self.codegen,
-            output: self.output,
+            #[cfg(feature = "llvm")]
+            codegen,
             options,
+            ..self
         }
     }
 
     fn new(input: Box<dyn Read>, output: Box<dyn Write>, context: &'ctx Context) -> Self {
-        let builder = context.create_builder();
-        let module = context.create_module("Kaleidoscope");
         Driver {
             parser: Parser::new(),
             lexer: Lexer::new(input),
-            options: DriverOptions {
-                print_parse: false,
-                print_ir: false,
-            },
-            codegen: CodeGen::new(context, builder, module),
+            options: DriverOptions::default(),
+            context,
+            #[cfg(feature = "llvm")]
+            codegen: None,
             output,
+            error_output: Box::new(std::io::stderr()),
+            source_name: "<stdin>".into(),
+            show_prompt: true,
+            errors: vec![],
+            last_value: None,
+            definitions_only: false,
+            #[cfg(feature = "llvm")]
+            engine: None,
+            #[cfg(feature = "llvm")]
+            symbols: HashMap::new(),
+            #[cfg(feature = "llvm")]
+            symbol_count: 0,
+            #[cfg(feature = "llvm")]
+            missing_externs: HashMap::new(),
+            interpreter: Interpreter::new(),
         }
     }
     fn run(&mut self) -> Result<(), std::io::Error> {
-        Self::shim_lib_functions();
+        #[cfg(feature = "llvm")]
+        library::register_runtime_functions();
 
         loop {
-            write!(self.output, "ready> ")?;
-            self.output.flush()?;
+            if self.show_prompt {
+                write!(self.output, "ready> ")?;
+                self.output.flush()?;
+            }
             self.lexer.get_next_token();
 
             match self.lexer.current_token() {
@@ -91,23 +228,28 @@ where
                 Some(Token::Misc(';')) => self.lexer.get_next_token().discard(),
                 Some(Token::Def) => self.handle_function_definition()?,
                 Some(Token::Extern) => self.handle_extern()?,
+                _ if self.definitions_only => {
+                    let diagnostic = self.expected_definition();
+                    self.handle_parse_error(&diagnostic)?
+                }
                 _ => self.handle_top_level_expression()?,
             }
 
+            self.report_warnings()?;
+
+            // A missing ';' at the very end of the input is harmless
             match self.lexer.current_token() {
-                Some(Token::Misc(c)) => {
-                    if *c != ';' {
-                        writeln!(self.output, "Expected ';', but got {}", *c)?;
-                    }
+                Some(Token::Misc(';')) | Some(Token::EOF) => (),
+                _ => {
+                    let diagnostic = self.expected_semicolon();
+                    self.report(&diagnostic)?;
                 }
-                Some(tok) => writeln!(self.output, "Expected ';', but got {:#?}", tok)?,
-                None => writeln!(self.output, "Expected ';', but got nothing...")?,
             }
         }
     }
     fn handle_function_definition(&mut self) -> Result<(), std::io::Error> {
         match self.parser.parse_function_definition(&mut self.lexer) {
-            Some(expr) => {
+            Ok(expr) => {
                 if self.options.print_parse {
                     writeln!(self.output, "Parsed a function definition")?;
                     writeln!(self.output, "{:#?}", expr)?;
@@ -115,21 +257,13 @@ where
                 }
                 Ok(self.handle_function_codegen(&expr, false)?)
             }
-            None => {
-                writeln!(
-                    self.output,
-                    "Failed to parse function definition, continuing..."
-                )?;
-                self.output.flush()?;
-                self.lexer.get_next_token().discard();
-                Ok(())
-            }
+            Err(diagnostic) => self.handle_parse_error(&diagnostic),
         }
     }
 
     fn handle_extern(&mut self) -> Result<(), std::io::Error> {
         match self.parser.parse_extern(&mut self.lexer) {
-            Some(expr) => {
+            Ok(expr) => {
                 if self.options.print_parse {
                     writeln!(self.output, "Parsed an extern")?;
                     writeln!(self.output, "{:#?}", expr)?;
@@ -137,18 +271,13 @@ where
                 }
                 Ok(self.handle_prototype_codegen(&expr)?)
             }
-            None => {
-                writeln!(self.output, "Failed to parse extern, continuing...")?;
-                self.output.flush()?;
-                self.lexer.get_next_token().discard();
-                Ok(())
-            }
+            Err(diagnostic) => self.handle_parse_error(&diagnostic),
         }
     }
 
     fn handle_top_level_expression(&mut self) -> Result<(), std::io::Error> {
         match self.parser.parse_top_level_expression(&mut self.lexer) {
-            Some(expr) => {
+            Ok(expr) => {
                 if self.options.print_parse {
                     writeln!(self.output, "Parsed a top level expression")?;
                     writeln!(self.output, "{:#?}", expr)?;
@@ -156,104 +285,256 @@ where
                 }
                 Ok(self.handle_function_codegen(&expr, true)?)
             }
-            None => {
-                writeln!(
-                    self.output,
-                    "Failed to parse top level definition, continuing..."
-                )?;
-                self.output.flush()?;
-                self.lexer.get_next_token().discard();
-                Ok(())
-            }
+            Err(diagnostic) => self.handle_parse_error(&diagnostic),
         }
     }
 }
 
-impl Driver<'_> {
+impl<'a> Driver<'a> {
     fn handle_function_codegen(
         &mut self,
         expr: &Expr,
         is_anonymous: bool,
     ) -> Result<(), std::io::Error> {
-        match &expr.kind {
-            ExprKind::Function { prototype, body } => {
-                let result = self.codegen.codegen_function(prototype, body);
-
-                if self.options.print_ir {
-                    let result_as_str = result
-                        .map_or("Failed to codegen function, continuing...".into(), |ir| {
-                            ir.print_to_string().to_string()
-                        });
-                    writeln!(self.output, "{}", result_as_str)?;
-                    self.output.flush()?;
-                }
+        match self.options.backend {
+            #[cfg(feature = "llvm")]
+            Backend::Jit => self.handle_function_jit(expr, is_anonymous),
+            Backend::Interp => self.handle_function_interpretation(expr, is_anonymous),
+        }
+    }
 
-                if !is_anonymous || result.is_none() {
-                    return Ok(());
-                }
-                let result = result.unwrap();
-
-                let engine = self
-                    .codegen
-                    .module
-                    .create_jit_execution_engine(OptimizationLevel::Aggressive)
-                    .unwrap();
-
-                defer!(
-                    engine.remove_module(&self.codegen.module).unwrap();
-                );
-
-                let fun = unsafe {
-                    engine.get_function::<unsafe extern "C" fn() -> f64>(
-                        result.get_name().to_str().unwrap(),
-                    )
-                };
-
-                if fun.is_err() {
-                    return Ok(());
-                }
+    fn handle_prototype_codegen(&mut self, expr: &Expr) -> Result<(), std::io::Error> {
+        match self.options.backend {
+            #[cfg(feature = "llvm")]
+            Backend::Jit => self.handle_prototype_jit(expr),
+            Backend::Interp => match self.interpreter.declare_extern(expr) {
+                Ok(()) => Ok(()),
+                Err(diagnostic) => self.report(&diagnostic),
+            },
+        }
+    }
 
-                let fun = fun.unwrap();
-                let result = unsafe { fun.call() };
-                eprintln!("Evaluated to {}\n", result);
-            }
+    fn handle_function_interpretation(
+        &mut self,
+        expr: &Expr,
+        is_anonymous: bool,
+    ) -> Result<(), std::io::Error> {
+        let (prototype, body) = match &expr.kind {
+            ExprKind::Function { prototype, body } => (prototype, body),
             _ => {
-                writeln!(self.output, "Failed to codegen function, continuing...")?;
-                self.output.flush()?;
+                writeln!(
+                    self.error_output,
+                    "Failed to interpret function, continuing..."
+                )?;
+                return self.error_output.flush();
+            }
+        };
+
+        if !is_anonymous {
+            return match self.interpreter.define_function(prototype, body) {
+                Ok(()) => Ok(()),
+                Err(diagnostic) => self.report(&diagnostic),
+            };
+        }
+
+        match self.interpreter.evaluate(body) {
+            Ok(result) => {
+                self.last_value = Some(result);
+                writeln!(self.output, "Evaluated to {}\n", result)?;
+                self.output.flush()
             }
+            Err(diagnostic) => self.report(&diagnostic),
+        }
+    }
+
+    // Anything that needs generated code, which the interpreter never has
+    fn report_requires_jit(&mut self, what: &str) -> Result<(), std::io::Error> {
+        let diagnostic = Diagnostic::error(
+            code::REQUIRES_JIT,
+            format!("{} requires the jit backend", what),
+            Span::default(),
+        )
+        .with_note(INTERPRETER_NOTE);
+        self.report(&diagnostic)
+    }
+
+    /// Sends diagnostics somewhere other than stderr
+    pub fn with_error_output(self, error_output: Box<dyn Write>) -> Self {
+        Self {
+            error_output,
+            ..self
+        }
+    }
+
+    /// Whether `run` prompts for each item, which only makes sense when a person is typing them
+    pub fn with_prompt(self, show_prompt: bool) -> Self {
+        Self {
+            show_prompt,
+            ..self
+        }
+    }
+
+    /// Makes functions from the host process and any libraries passed to --load callable
+    /// through `extern`
+    #[cfg(feature = "llvm")]
+    pub fn load_libraries(&self) -> Result<(), std::io::Error> {
+        library::load_host_symbols()?;
+        for path in &self.options.load {
+            library::load_library(path)?;
         }
         Ok(())
     }
 
-    fn handle_prototype_codegen(&mut self, expr: &Expr) -> Result<(), std::io::Error> {
-        match &expr.kind {
-            ExprKind::Prototype { name, args } => {
-                let result = self.codegen.codegen_prototype(args, name);
-                if self.options.print_ir {
-                    let result = result.print_to_string().to_string();
-                    writeln!(self.output, "{}", result)?;
-                    self.output.flush()?;
-                }
-            }
-            _ => {
-                writeln!(self.output, "Failed to codegen extern, continuing...")?;
-                self.output.flush()?;
-                self.lexer.get_next_token();
+    /// The interpreter can only call runtime and math library functions, so there's nothing to
+    /// load without the llvm feature
+    #[cfg(not(feature = "llvm"))]
+    pub fn load_libraries(&self) -> Result<(), std::io::Error> {
+        match self.options.load.first() {
+            Some(path) => Err(Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "{}: loading libraries requires the llvm feature",
+                    path.display()
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_parse_error(&mut self, diagnostic: &Diagnostic) -> Result<(), std::io::Error> {
+        self.report(diagnostic)?;
+        self.report_warnings()?;
+
+        // Skip the rest of the statement so one mistake is only reported once. The ';' ending it
+        // is the first one outside of any block, including those the error was inside of
+        let mut open_blocks = self.parser.take_open_blocks();
+        loop {
+            match self.lexer.current_token() {
+                Some(Token::EOF) | None => break,
+                Some(Token::Misc(';')) if open_blocks == 0 => break,
+                Some(Token::Misc('{')) => open_blocks += 1,
+                Some(Token::Misc('}')) => open_blocks = open_blocks.saturating_sub(1),
+                _ => (),
             }
+            self.lexer.get_next_token();
         }
         Ok(())
     }
 
-    pub fn dump_ir(&mut self) -> Result<(), std::io::Error> {
-        if !self.options.print_ir {
-            return Ok(());
+    fn expected_definition(&self) -> Diagnostic {
+        let got = match self.lexer.current_token() {
+            Some(tok) => tok.to_string(),
+            None => "nothing".into(),
+        };
+        Diagnostic::error(
+            code::UNEXPECTED_TOKEN,
+            format!("Expected 'def' or 'extern', got {}", got),
+            self.lexer.current_span(),
+        )
+        .with_note("only definitions are allowed here, top level expressions aren't run")
+    }
+
+    fn expected_semicolon(&self) -> Diagnostic {
+        let got = match self.lexer.current_token() {
+            Some(tok) => tok.to_string(),
+            None => "nothing".into(),
+        };
+        Diagnostic::error(
+            code::UNEXPECTED_TOKEN,
+            format!("Expected ';', got {}", got),
+            self.lexer.current_span(),
+        )
+    }
+
+    fn report_warnings(&mut self) -> Result<(), std::io::Error> {
+        for warning in self.parser.take_warnings() {
+            self.report(&warning)?;
         }
-        let llvm_string = self.codegen.module.print_to_string();
-        let as_str = llvm_string
-            .to_str()
-            .ok()
-            .map_or("Failed to dump Module IR", |s| s);
-        writeln!(self.output, "{}", as_str)?;
         Ok(())
     }
+
+    fn report(&mut self, diagnostic: &Diagnostic) -> Result<(), std::io::Error> {
+        if diagnostic.severity == Severity::Error {
+            self.errors.push(diagnostic.clone());
+        }
+        // The lexer stops wherever the error is, the rest of the line is still worth quoting
+        self.lexer.read_rest_of_line();
+        let rendered = diagnostic.render(&self.source_name, self.lexer.source());
+        write!(self.error_output, "{}", rendered)?;
+        self.error_output.flush()
+    }
+
+    /// Runs a source file without prompting, anything it defines stays around for later input
+    pub fn run_file(&mut self, path: &Path) -> Result<(), std::io::Error> {
+        let file = File::open(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+        self.run_input(Box::new(BufReader::new(file)), path.display().to_string())
+    }
+
+    /// Runs a complete entry typed into the REPL
+    pub fn run_entry(&mut self, entry: &str) -> Result<(), std::io::Error> {
+        self.run_input(Box::new(Cursor::new(entry.to_owned())), "<repl>".into())
+    }
+
+    fn run_input(
+        &mut self,
+        input: Box<dyn Read>,
+        source_name: String,
+    ) -> Result<(), std::io::Error> {
+        self.lexer = Lexer::new(input);
+        self.source_name = source_name;
+        self.last_value = None;
+        self.show_prompt = false;
+        self.run()
+    }
+
+    /// How many errors have been reported so far
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Every error reported so far, oldest first
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    /// What the latest top level expression in the last input evaluated to, if one ran
+    pub fn last_value(&self) -> Option<f64> {
+        self.last_value
+    }
+
+    /// Calls a function defined earlier with whichever backend is in use
+    pub fn call_function(&mut self, name: &str, args: &[f64]) -> Result<f64, Diagnostic> {
+        match self.options.backend {
+            #[cfg(feature = "llvm")]
+            Backend::Jit => self.call_jit_function(name, args),
+            Backend::Interp => self.interpreter.call_function(name, args, Span::default()),
+        }
+    }
+
+    pub fn dump_ir(&mut self) -> Result<(), std::io::Error> {
+        match (self.options.print_ir, self.options.backend) {
+            (false, _) => Ok(()),
+            #[cfg(feature = "llvm")]
+            (true, Backend::Jit) => self.dump_program_ir(),
+            (true, Backend::Interp) => self.report_requires_jit("--print-ir"),
+        }
+    }
+
+    pub fn emit(&mut self) -> Result<(), std::io::Error> {
+        match (self.options.emit, self.options.backend) {
+            (None, _) => Ok(()),
+            #[cfg(feature = "llvm")]
+            (Some(kind), Backend::Jit) => self.emit_program(kind),
+            (Some(_), Backend::Interp) => self.report_requires_jit("--emit"),
+        }
+    }
 }
+
+#[cfg(all(test, feature = "llvm"))]
+mod differential_tests;
+#[cfg(all(test, feature = "llvm"))]
+mod golden_tests;
+#[cfg(all(test, feature = "llvm"))]
+mod tests;
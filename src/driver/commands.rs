@@ -0,0 +1,174 @@
+use std::{io::Write, path::Path};
+
+#[cfg(feature = "llvm")]
+use inkwell::values::AnyValue;
+
+#[cfg(feature = "llvm")]
+use crate::codegen;
+use crate::{
+    interpreter::Interpreter,
+    lexer::{Lex, Lexer},
+    parser::{Parse, Parser},
+};
+
+use super::{Backend, Driver};
+
+const HELP: &str = "\
+:ir [fn]               print the IR of a function, or of the whole module
+:ast <expr>            print the AST of an expression without running it
+:ops                   list binary operators and their precedences
+:funcs                 list the functions that have been defined or declared
+:set <option> on|off   toggle print-ir or print-parse
+:reset                 forget every function and operator defined so far
+:load <file>           run a source file
+:help                  print this message";
+
+impl Driver<'_> {
+    /// Handles a REPL meta-command, `command` is the whole line including the leading ':'
+    pub fn handle_command(&mut self, command: &str) -> Result<(), std::io::Error> {
+        let command = command.trim().trim_start_matches(':');
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+
+        match name {
+            "ir" | "funcs" if self.options.backend == Backend::Interp => {
+                self.report_requires_jit(&format!(":{}", name))?
+            }
+            #[cfg(feature = "llvm")]
+            "ir" => self.command_ir(argument)?,
+            "ast" => self.command_ast(argument)?,
+            "ops" => self.command_ops()?,
+            #[cfg(feature = "llvm")]
+            "funcs" => self.command_funcs()?,
+            "set" => self.command_set(argument)?,
+            "reset" => self.command_reset()?,
+            "load" => {
+                // A typo in the path shouldn't end the session
+                if let Err(e) = self.run_file(Path::new(argument)) {
+                    writeln!(self.error_output, "{}", e)?;
+                }
+            }
+            "help" => writeln!(self.output, "{}", HELP)?,
+            _ => writeln!(
+                self.output,
+                "Unknown command :{}, try :help to see what's available",
+                name
+            )?,
+        }
+        self.output.flush()
+    }
+
+    #[cfg(feature = "llvm")]
+    fn command_ir(&mut self, function_name: &str) -> Result<(), std::io::Error> {
+        let program = match self.codegen().program_module() {
+            Ok(program) => program,
+            Err(diagnostic) => return self.report(&diagnostic),
+        };
+        if function_name.is_empty() {
+            let ir = program.print_to_string().to_string();
+            return writeln!(self.output, "{}", ir);
+        }
+
+        match program.get_function(function_name) {
+            Some(function) => writeln!(self.output, "{}", function.print_to_string().to_string()),
+            None => writeln!(self.output, "Unknown function {}", function_name),
+        }
+    }
+
+    fn command_ast(&mut self, source: &str) -> Result<(), std::io::Error> {
+        let mut lexer = Lexer::new(source.as_bytes());
+        lexer.get_next_token();
+
+        match self.parser.parse_expression(&mut lexer) {
+            Ok(expr) => writeln!(self.output, "{:#?}", expr),
+            Err(diagnostic) => {
+                lexer.read_rest_of_line();
+                let rendered = diagnostic.render("<ast>", lexer.source());
+                write!(self.error_output, "{}", rendered)
+            }
+        }
+    }
+
+    fn command_ops(&mut self) -> Result<(), std::io::Error> {
+        for (operator, precedence) in self.parser.environment.operator_precedences() {
+            writeln!(self.output, "{} {}", operator, precedence)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "llvm")]
+    fn command_funcs(&mut self) -> Result<(), std::io::Error> {
+        let program = match self.codegen().program_module() {
+            Ok(program) => program,
+            Err(diagnostic) => return self.report(&diagnostic),
+        };
+        let functions: Vec<String> = program
+            .get_functions()
+            .filter(|function| !codegen::is_intrinsic(*function))
+            .map(|function| {
+                let params: Vec<String> = function
+                    .get_param_iter()
+                    .map(|param| {
+                        param
+                            .into_float_value()
+                            .get_name()
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect();
+                // Externs have no body
+                let kind = if function.count_basic_blocks() == 0 {
+                    "extern"
+                } else {
+                    "def"
+                };
+                format!(
+                    "{} {}({})",
+                    kind,
+                    function.get_name().to_string_lossy(),
+                    params.join(" ")
+                )
+            })
+            .collect();
+
+        for function in functions {
+            writeln!(self.output, "{}", function)?;
+        }
+        Ok(())
+    }
+
+    fn command_set(&mut self, argument: &str) -> Result<(), std::io::Error> {
+        let (option, value) = match argument.split_once(char::is_whitespace) {
+            Some((option, value)) => (option, value.trim()),
+            None => (argument, ""),
+        };
+        let value = match value {
+            "on" => true,
+            "off" => false,
+            _ => return writeln!(self.output, "Expected on or off, got '{}'", value),
+        };
+
+        match option {
+            "print-ir" => self.options.print_ir = value,
+            "print-parse" => self.options.print_parse = value,
+            _ => return writeln!(self.output, "Unknown option {}", option),
+        }
+        Ok(())
+    }
+
+    fn command_reset(&mut self) -> Result<(), std::io::Error> {
+        #[cfg(feature = "llvm")]
+        {
+            // A fresh one is created the next time something is compiled
+            self.codegen = None;
+            // Old definitions stay compiled in the JIT, they're just never called again
+            self.symbols.clear();
+        }
+        self.parser = Parser::new();
+        self.interpreter = Interpreter::new();
+
+        writeln!(self.output, "Session reset")
+    }
+}
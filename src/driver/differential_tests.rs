@@ -0,0 +1,313 @@
+// Runs randomly generated programs through both backends and checks they agree. proptest shrinks
+// any disagreement down to a minimal program before reporting it
+
+use inkwell::context::Context;
+use proptest::prelude::*;
+
+use super::*;
+use crate::{
+    ast::{ForVal, IfVal, VarVal, WhileVal},
+    test_utilities::test::approx_equal,
+};
+
+// Keeps generated programs small enough to read once they've been shrunk
+const MAX_DEPTH: u32 = 4;
+// Loops always count up from zero to a small constant, so every program terminates
+const MAX_LOOP_END: u8 = 3;
+// Assignments only target the arguments, a loop variable is never changed by its body
+const ASSIGNABLE: [&str; 2] = ["a", "b"];
+// Variables only loop bodies can see, `i` for `for` loops and `w` for the counter in `while` loops
+const LOOP_VARIABLES: [&str; 2] = ["i", "w"];
+
+#[derive(Debug, Clone)]
+struct Program {
+    // `f(a b)`, which the body of `g` can call
+    helper: Expr,
+    // `g(a b)`, the function the test calls
+    body: Expr,
+}
+
+impl Program {
+    fn source(&self) -> String {
+        format!(
+            "def f(a b) {};\ndef g(a b) {};\n",
+            source(&self.helper),
+            source(&self.body)
+        )
+    }
+}
+
+fn make(kind: ExprKind) -> Expr {
+    Expr {
+        kind,
+        span: Span::default(),
+    }
+}
+
+fn variable(name: &str) -> Expr {
+    make(ExprKind::Variable { name: name.into() })
+}
+
+fn binary(operator: &str, lhs: Expr, rhs: Expr) -> Expr {
+    make(ExprKind::Binary {
+        operator: operator.into(),
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    })
+}
+
+// Turns a generated expression back into source, with everything parenthesized so precedence
+// never gets in the way
+fn source(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Number(n) => n.to_string(),
+        ExprKind::Variable { name } => name.clone(),
+        ExprKind::Binary { operator, lhs, rhs } => {
+            format!("({} {} {})", source(lhs), operator, source(rhs))
+        }
+        // The space keeps `not` from running into the operand
+        ExprKind::Unary { operator, operand } => format!("({} {})", operator, source(operand)),
+        ExprKind::Call { callee, args } => {
+            let args: Vec<String> = args.iter().map(source).collect();
+            format!("{}({})", callee, args.join(", "))
+        }
+        ExprKind::If(if_val) => format!(
+            "(if {} then {} else {})",
+            source(&if_val.if_boolish_test),
+            source(&if_val.then),
+            source(&if_val.elves)
+        ),
+        ExprKind::For(for_val) => {
+            let step = match &for_val.step {
+                Some(step) => format!(", {}", source(step)),
+                None => "".into(),
+            };
+            format!(
+                "(for {} = {}, {}{} in {})",
+                for_val.var_name,
+                source(&for_val.start),
+                source(&for_val.end),
+                step,
+                source(&for_val.body)
+            )
+        }
+        ExprKind::Var(var_val) => {
+            let bindings: Vec<String> = var_val
+                .bindings
+                .iter()
+                .map(|(name, init)| match init {
+                    Some(init) => format!("{} = {}", name, source(init)),
+                    None => name.clone(),
+                })
+                .collect();
+            format!("(var {} in {})", bindings.join(", "), source(&var_val.body))
+        }
+        ExprKind::While(while_val) => format!(
+            "(while {} do {})",
+            source(&while_val.condition),
+            source(&while_val.body)
+        ),
+        ExprKind::Break => "break".into(),
+        ExprKind::Block(expressions) => {
+            let expressions: Vec<String> = expressions.iter().map(source).collect();
+            format!("{{ {} }}", expressions.join("; "))
+        }
+        ExprKind::Continue => "continue".into(),
+        ExprKind::Prototype { .. } | ExprKind::Function { .. } => {
+            unreachable!("functions are never generated inside expressions")
+        }
+    }
+}
+
+fn leaf(vars: Vec<&'static str>) -> BoxedStrategy<Expr> {
+    prop_oneof![
+        (0u8..20).prop_map(|n| make(ExprKind::Number(f64::from(n) / 2.0))),
+        prop::sample::select(vars).prop_map(variable),
+    ]
+    .boxed()
+}
+
+// Well formed expressions that only refer to variables in `vars`, and call `f` if `can_call`
+fn expr(vars: &[&'static str], depth: u32, can_call: bool) -> BoxedStrategy<Expr> {
+    if depth == 0 {
+        return leaf(vars.to_vec());
+    }
+
+    let sub = expr(vars, depth - 1, can_call);
+    let with = |name| {
+        let mut vars = vars.to_vec();
+        if !vars.contains(&name) {
+            vars.push(name);
+        }
+        expr(&vars, depth - 1, can_call)
+    };
+    let in_loop = with("i");
+    let in_while = with("w");
+    let in_var = with("c");
+
+    let binary_op = (
+        prop::sample::select(vec![
+            "+", "-", "*", "/", "%", "^", "<", ">", "<=", ">=", "==", "!=", "and", "or",
+        ]),
+        sub.clone(),
+        sub.clone(),
+    )
+        .prop_map(|(operator, lhs, rhs)| binary(operator, lhs, rhs));
+    let assignment = (prop::sample::select(ASSIGNABLE.to_vec()), sub.clone())
+        .prop_map(|(name, value)| binary("=", variable(name), value));
+    let unary_op =
+        (prop::sample::select(vec!["-", "not"]), sub.clone()).prop_map(|(operator, operand)| {
+            make(ExprKind::Unary {
+                operator: operator.into(),
+                operand: Box::new(operand),
+            })
+        });
+    let if_else = (sub.clone(), sub.clone(), sub.clone()).prop_map(|(test, then, elves)| {
+        make(ExprKind::If(IfVal {
+            if_boolish_test: Box::new(test),
+            then: Box::new(then),
+            elves: Box::new(elves),
+        }))
+    });
+    let for_loop =
+        (0..=MAX_LOOP_END, prop::option::of(1u8..3), in_loop).prop_map(|(end, step, body)| {
+            let end = binary("<", variable("i"), make(ExprKind::Number(f64::from(end))));
+            make(ExprKind::For(ForVal {
+                var_name: "i".into(),
+                start: Box::new(make(ExprKind::Number(0.0))),
+                end: Box::new(end),
+                step: step.map(|step| Box::new(make(ExprKind::Number(f64::from(step))))),
+                body: Box::new(body),
+            }))
+        });
+    // The counter goes up before the body runs, so continue can't skip it
+    let while_loop = (1..=MAX_LOOP_END, in_while).prop_map(|(end, body)| {
+        let step = binary(
+            "=",
+            variable("w"),
+            binary("+", variable("w"), make(ExprKind::Number(1.0))),
+        );
+        let condition = binary("<=", step, make(ExprKind::Number(f64::from(end))));
+        let while_loop = make(ExprKind::While(WhileVal {
+            condition: Box::new(condition),
+            body: Box::new(body),
+        }));
+        var("w", make(ExprKind::Number(0.0)), while_loop)
+    });
+    // Binding `a` shadows the argument, binding `c` introduces a new variable
+    let var_shadowing = (sub.clone(), sub.clone()).prop_map(|(init, body)| var("a", init, body));
+    let var_new = (sub.clone(), in_var).prop_map(|(init, body)| var("c", init, body));
+
+    let block = prop::collection::vec(sub.clone(), 1..4)
+        .prop_map(|expressions| make(ExprKind::Block(expressions)));
+    let call = (sub.clone(), sub).prop_map(|(x, y)| {
+        make(ExprKind::Call {
+            callee: "f".into(),
+            args: vec![x, y],
+        })
+    });
+    let call = if can_call {
+        call.boxed()
+    } else {
+        leaf(vars.to_vec())
+    };
+    let loop_control = if vars.iter().any(|name| LOOP_VARIABLES.contains(name)) {
+        prop_oneof![Just(make(ExprKind::Break)), Just(make(ExprKind::Continue))].boxed()
+    } else {
+        leaf(vars.to_vec())
+    };
+
+    prop_oneof![
+        3 => leaf(vars.to_vec()),
+        3 => binary_op,
+        1 => assignment,
+        1 => unary_op,
+        2 => if_else,
+        1 => for_loop,
+        1 => while_loop,
+        1 => var_shadowing,
+        1 => var_new,
+        1 => call,
+        1 => loop_control,
+        1 => block,
+    ]
+    .boxed()
+}
+
+fn var(name: &str, init: Expr, body: Expr) -> Expr {
+    make(ExprKind::Var(VarVal {
+        bindings: vec![(name.into(), Some(Box::new(init)))],
+        body: Box::new(body),
+    }))
+}
+
+fn program() -> impl Strategy<Value = Program> {
+    (
+        expr(&["a", "b"], MAX_DEPTH - 1, false),
+        expr(&["a", "b"], MAX_DEPTH, true),
+    )
+        .prop_map(|(helper, body)| Program { helper, body })
+}
+
+fn run_jit(source: &str, a: f64, b: f64) -> Result<f64, String> {
+    let context = Context::create();
+    let mut driver = Driver::new(Box::new(std::io::empty()), Box::new(Vec::new()), &context)
+        .with_error_output(Box::new(std::io::sink()));
+    driver.run_entry(source).map_err(|e| e.to_string())?;
+    if driver.error_count() > 0 {
+        return Err("the JIT reported errors".into());
+    }
+
+    driver
+        .call_function("g", &[a, b])
+        .map_err(|diagnostic| diagnostic.message)
+}
+
+fn run_interpreter(source: &str, a: f64, b: f64) -> Result<f64, String> {
+    let context = Context::create();
+    let driver = Driver::new(Box::new(std::io::empty()), Box::new(Vec::new()), &context)
+        .with_error_output(Box::new(std::io::sink()));
+    let mut driver = driver.with_options(DriverOptions {
+        backend: Backend::Interp,
+        ..Default::default()
+    });
+    driver.run_entry(source).map_err(|e| e.to_string())?;
+    if driver.error_count() > 0 {
+        return Err("the interpreter reported errors".into());
+    }
+
+    driver
+        .interpreter
+        .call_function("g", &[a, b], Span::default())
+        .map_err(|diagnostic| diagnostic.message)
+}
+
+fn same_result(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan()) || approx_equal(a, b, 6)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_interpreter_agrees_with_jit(program in program(), a in 0u8..10, b in 0u8..10) {
+        let source = program.source();
+        let (a, b) = (f64::from(a), f64::from(b));
+
+        let jitted = run_jit(&source, a, b);
+        let interpreted = run_interpreter(&source, a, b);
+        prop_assert!(jitted.is_ok(), "{}\n{:?}", source, jitted);
+        prop_assert!(interpreted.is_ok(), "{}\n{:?}", source, interpreted);
+
+        let (jitted, interpreted) = (jitted.unwrap(), interpreted.unwrap());
+        prop_assert!(
+            same_result(jitted, interpreted),
+            "g({}, {}) evaluated to {} with the JIT but {} with the interpreter\n{}",
+            a,
+            b,
+            jitted,
+            interpreted,
+            source
+        );
+    }
+}
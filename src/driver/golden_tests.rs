@@ -0,0 +1,124 @@
+// Runs every program in tests/programs and compares what it printed with the files next to it:
+// `.stdout` for what top level expressions evaluated to, `.stderr` for diagnostics and `.ll` for
+// the IR of the whole program. A missing `.stdout` or `.stderr` means nothing is expected, IR is
+// only checked for programs that have a `.ll`.
+//
+// After an intended change in output, bless the new expectations instead of editing them by hand:
+//
+//     KALEIDOSCOPE_BLESS=1 cargo test golden
+//
+// which writes what the programs print now over the files next to them. Check the diff before
+// committing it. This is an environment variable because the standard test harness rejects any
+// flag it doesn't know, so a `--bless` flag couldn't be passed
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use inkwell::context::Context;
+
+use super::tests::SharedOutput;
+use super::*;
+
+const PROGRAMS_DIR: &str = "tests/programs";
+const BLESS_VAR: &str = "KALEIDOSCOPE_BLESS";
+// A comment on the first line of a program can pass it command line flags
+const FLAGS_PREFIX: &str = "# flags:";
+
+fn programs() -> Vec<PathBuf> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(PROGRAMS_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "kal")
+        })
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn options_for(source: &str) -> DriverOptions {
+    let flags = source
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix(FLAGS_PREFIX))
+        .unwrap_or("");
+    let args = std::iter::once("kaleidoscope").chain(flags.split_whitespace());
+    <DriverOptions as clap::Parser>::try_parse_from(args).unwrap()
+}
+
+// Runs a program the way the command line would, returning its stdout, stderr and IR
+fn run_program(path: &Path) -> (String, String, String) {
+    let options = options_for(&fs::read_to_string(path).unwrap());
+    let context = Context::create();
+    let (stdout, stderr) = (SharedOutput::default(), SharedOutput::default());
+    let mut driver = Driver::new(
+        Box::new(std::io::empty()),
+        Box::new(stdout.clone()),
+        &context,
+    )
+    .with_options(options)
+    .with_error_output(Box::new(stderr.clone()));
+
+    driver.run_file(path).unwrap();
+    driver.dump_ir().unwrap();
+    let ir = match driver.codegen().program_module() {
+        Ok(program) => program.print_to_string().to_string(),
+        Err(diagnostic) => diagnostic.message,
+    };
+
+    (stdout.contents(), stderr.contents(), ir)
+}
+
+// Compares `actual` with the expectation in `path`, or replaces it when blessing. Returns a
+// description of any mismatch
+fn check(path: &Path, actual: &str, bless: bool) -> Option<String> {
+    let expected = fs::read_to_string(path).unwrap_or_default();
+    if expected == actual {
+        return None;
+    }
+
+    if bless {
+        if actual.is_empty() {
+            fs::remove_file(path).unwrap();
+        } else {
+            fs::write(path, actual).unwrap();
+        }
+        return None;
+    }
+
+    Some(format!(
+        "{} doesn't match\n--- expected\n{}\n--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    ))
+}
+
+#[test]
+fn test_golden_programs() {
+    let bless = std::env::var_os(BLESS_VAR).is_some();
+    let programs = programs();
+    assert!(!programs.is_empty(), "No programs in {}", PROGRAMS_DIR);
+
+    let mut mismatches = vec![];
+    for program in &programs {
+        let (stdout, stderr, ir) = run_program(program);
+        mismatches.extend(check(&program.with_extension("stdout"), &stdout, bless));
+        mismatches.extend(check(&program.with_extension("stderr"), &stderr, bless));
+
+        let ir_path = program.with_extension("ll");
+        if ir_path.exists() {
+            mismatches.extend(check(&ir_path, &ir, bless));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\nRun `{}=1 cargo test golden` to accept the new output",
+        mismatches.join("\n"),
+        BLESS_VAR
+    );
+}
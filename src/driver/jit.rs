@@ -0,0 +1,420 @@
+use inkwell::{
+    execution_engine::ExecutionEngine,
+    module::Module,
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+    },
+    values::AnyValue,
+    OptimizationLevel,
+};
+
+use crate::{
+    ast::{Expr, ExprKind},
+    codegen::{self, CodeGen},
+    diagnostic::{code, Diagnostic},
+    lexer::Span,
+    library,
+};
+
+use std::{
+    io::{Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use super::{Driver, DriverOptions, EmitKind};
+
+// How many arguments `Driver::call_function` can pass to JIT compiled code
+const MAX_CALL_ARGS: usize = 4;
+
+// Everything the JIT backend does, none of which exists without the llvm feature
+impl<'a> Driver<'a> {
+    pub(super) fn handle_function_jit(
+        &mut self,
+        expr: &Expr,
+        is_anonymous: bool,
+    ) -> Result<(), std::io::Error> {
+        match &expr.kind {
+            ExprKind::Function { prototype, body } => {
+                let result = self.codegen().codegen_function(prototype, body);
+                // Whatever happened, the next item gets a module of its own
+                let module = self.codegen().take_module();
+                let result = match result {
+                    Ok(result) => result,
+                    Err(diagnostic) => return self.report(&diagnostic),
+                };
+
+                if self.options.print_ir {
+                    writeln!(self.output, "{}", result.print_to_string().to_string())?;
+                    self.output.flush()?;
+                }
+
+                // An executable is only ever emitted, so nothing needs the JIT. Its top level
+                // expressions are left out, which is worth saying since they'd run anywhere else
+                if self.options.executable {
+                    if is_anonymous {
+                        let diagnostic = Diagnostic::warning(
+                            code::IGNORED_TOP_LEVEL_EXPRESSION,
+                            "Top level expression isn't run by an executable",
+                            expr.span,
+                        )
+                        .with_note("executables only run `def main()`, call it from there instead");
+                        self.report(&diagnostic)?;
+                    }
+                    return Ok(());
+                }
+
+                let missing_externs = self.add_to_jit(&module)?;
+                if !is_anonymous {
+                    return Ok(());
+                }
+
+                if !missing_externs.is_empty() {
+                    self.engine()?.remove_module(&module).ok();
+                    for name in missing_externs {
+                        let diagnostic = Diagnostic::error(
+                            code::UNRESOLVED_EXTERN,
+                            format!("Unable to resolve external function {}", name),
+                            expr.span,
+                        )
+                        .with_note(
+                            "externs have to be defined, or come from a library passed to --load",
+                        );
+                        self.report(&diagnostic)?;
+                    }
+                    return Ok(());
+                }
+
+                let symbol = result.get_name().to_string_lossy().into_owned();
+                let fun = unsafe {
+                    self.engine()?
+                        .get_function::<unsafe extern "C" fn() -> f64>(&symbol)
+                };
+                if let Ok(fun) = fun {
+                    let result = unsafe { fun.call() };
+                    self.last_value = Some(result);
+                    writeln!(self.output, "Evaluated to {}\n", result)?;
+                    self.output.flush()?;
+                }
+
+                // Nothing can call a top level expression again, so its IR can go
+                self.engine()?.remove_module(&module).map_err(|e| {
+                    Error::new(
+                        ErrorKind::Other,
+                        format!("Unable to remove module from the JIT: {:?}", e),
+                    )
+                })?;
+            }
+            _ => {
+                writeln!(
+                    self.error_output,
+                    "Failed to codegen function, continuing..."
+                )?;
+                self.error_output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn handle_prototype_jit(&mut self, expr: &Expr) -> Result<(), std::io::Error> {
+        match &expr.kind {
+            ExprKind::Prototype { name, args } => {
+                let result = self.codegen().codegen_prototype(args, name);
+                // A declaration has nothing to compile, it's declared again wherever it's called
+                let _module = self.codegen().take_module();
+                if self.options.print_ir {
+                    let result = result.print_to_string().to_string();
+                    writeln!(self.output, "{}", result)?;
+                    self.output.flush()?;
+                }
+            }
+            _ => {
+                writeln!(self.error_output, "Failed to codegen extern, continuing...")?;
+                self.error_output.flush()?;
+                self.lexer.get_next_token();
+            }
+        }
+        Ok(())
+    }
+
+    // Each definition gets a symbol of its own, otherwise a call could resolve to an older
+    // definition that the JIT has already compiled. Declarations are pointed at the latest one.
+    // Returns the externs the module needs that can't be found
+    fn add_to_jit(&mut self, module: &Module<'a>) -> Result<Vec<String>, std::io::Error> {
+        let mut missing_externs = vec![];
+        for function in module.get_functions() {
+            // LLVM provides intrinsics itself
+            if function.count_basic_blocks() > 0 || codegen::is_intrinsic(function) {
+                continue;
+            }
+            let name = function.get_name().to_string_lossy().into_owned();
+            match self.symbols.get(&name) {
+                Some(symbol) => {
+                    missing_externs.extend(self.missing_externs[symbol].iter().cloned());
+                }
+                None if !library::is_loaded(&name) => missing_externs.push(name),
+                None => (),
+            }
+        }
+        missing_externs.sort();
+        missing_externs.dedup();
+
+        for function in module.get_functions() {
+            let name = function.get_name().to_string_lossy().into_owned();
+            let symbol = if function.count_basic_blocks() > 0 {
+                self.symbol_count += 1;
+                let symbol = format!("{}.{}", name, self.symbol_count);
+                // Nothing can call a top level expression, and its module is removed once it's
+                // run, so there's nothing to remember about it
+                if name != codegen::ANONYMOUS_FUNCTION_NAME {
+                    self.symbols.insert(name, symbol.clone());
+                    self.missing_externs
+                        .insert(symbol.clone(), missing_externs.clone());
+                }
+                symbol
+            } else {
+                match self.symbols.get(&name) {
+                    Some(symbol) => symbol.clone(),
+                    // Library functions keep their names so they resolve to the host's symbols
+                    None => continue,
+                }
+            };
+            function
+                .as_global_value()
+                .as_pointer_value()
+                .set_name(&symbol);
+        }
+
+        self.engine()?
+            .add_module(module)
+            .map_err(|()| Error::new(ErrorKind::Other, "Unable to add module to the JIT"))?;
+        Ok(missing_externs)
+    }
+
+    pub(super) fn codegen(&mut self) -> &mut CodeGen<'a> {
+        let (context, options, source_name) = (self.context, &self.options, &self.source_name);
+        self.codegen.get_or_insert_with(|| {
+            let mut codegen = CodeGen::new(
+                context,
+                context.create_builder(),
+                context.create_module("Kaleidoscope"),
+            );
+            if options.debug_info {
+                enable_debug_info(&mut codegen, options, source_name);
+            }
+            codegen
+        })
+    }
+
+    // Started by the first thing compiled, so only the JIT backend needs one
+    fn engine(&mut self) -> Result<&ExecutionEngine<'a>, std::io::Error> {
+        let engine = match self.engine.take() {
+            Some(engine) => engine,
+            None => self
+                .context
+                .create_module("jit")
+                .create_jit_execution_engine(OptimizationLevel::Aggressive)
+                .map_err(|e| {
+                    Error::new(
+                        ErrorKind::Other,
+                        format!("Unable to create a JIT execution engine: {}", e),
+                    )
+                })?,
+        };
+        Ok(self.engine.insert(engine))
+    }
+
+    pub(super) fn call_jit_function(
+        &mut self,
+        name: &str,
+        args: &[f64],
+    ) -> Result<f64, Diagnostic> {
+        let unknown_function = || {
+            Diagnostic::error(
+                code::UNKNOWN_FUNCTION,
+                format!("Unknown function {}", name),
+                Span::default(),
+            )
+        };
+        let symbol = self.symbols.get(name).ok_or_else(unknown_function)?;
+        // Anything with a symbol has been added to the JIT, so it must have been started
+        let engine = self.engine.as_ref().ok_or_else(unknown_function)?;
+        if let Some(missing) = self.missing_externs[symbol].first() {
+            return Err(Diagnostic::error(
+                code::UNRESOLVED_EXTERN,
+                format!("Unable to resolve external function {}", missing),
+                Span::default(),
+            ));
+        }
+
+        let arg_count = engine
+            .get_function_value(symbol)
+            .map_err(|_| unknown_function())?
+            .count_params() as usize;
+        if arg_count != args.len() {
+            return Err(Diagnostic::error(
+                code::ARITY_MISMATCH,
+                format!("Incorrect number of arguments passed to {}", name),
+                Span::default(),
+            )
+            .with_note(format!(
+                "{} takes {} argument(s), but {} were given",
+                name,
+                arg_count,
+                args.len()
+            )));
+        }
+
+        unsafe { self.call_jitted(symbol, args) }.ok_or_else(|| {
+            Diagnostic::error(
+                code::ARITY_MISMATCH,
+                format!("Unable to call {} with {} arguments", name, args.len()),
+                Span::default(),
+            )
+            .with_note(format!(
+                "only functions taking up to {} arguments can be called this way",
+                MAX_CALL_ARGS
+            ))
+        })
+    }
+
+    // Every argument is a double, so the function's type only depends on how many there are
+    unsafe fn call_jitted(&self, symbol: &str, args: &[f64]) -> Option<f64> {
+        type Fn0 = unsafe extern "C" fn() -> f64;
+        type Fn1 = unsafe extern "C" fn(f64) -> f64;
+        type Fn2 = unsafe extern "C" fn(f64, f64) -> f64;
+        type Fn3 = unsafe extern "C" fn(f64, f64, f64) -> f64;
+        type Fn4 = unsafe extern "C" fn(f64, f64, f64, f64) -> f64;
+
+        let engine = self.engine.as_ref()?;
+        match *args {
+            [] => engine.get_function::<Fn0>(symbol).ok().map(|f| f.call()),
+            [a] => engine.get_function::<Fn1>(symbol).ok().map(|f| f.call(a)),
+            [a, b] => engine
+                .get_function::<Fn2>(symbol)
+                .ok()
+                .map(|f| f.call(a, b)),
+            [a, b, c] => engine
+                .get_function::<Fn3>(symbol)
+                .ok()
+                .map(|f| f.call(a, b, c)),
+            [a, b, c, d] => engine
+                .get_function::<Fn4>(symbol)
+                .ok()
+                .map(|f| f.call(a, b, c, d)),
+            _ => None,
+        }
+    }
+
+    pub(super) fn dump_program_ir(&mut self) -> Result<(), std::io::Error> {
+        let program = match self.codegen().program_module() {
+            Ok(program) => program,
+            Err(diagnostic) => return self.report(&diagnostic),
+        };
+        let llvm_string = program.print_to_string();
+        let as_str = llvm_string
+            .to_str()
+            .ok()
+            .map_or("Failed to dump Module IR", |s| s);
+        writeln!(self.output, "{}", as_str)?;
+        Ok(())
+    }
+
+    pub(super) fn emit_program(&mut self, kind: EmitKind) -> Result<(), std::io::Error> {
+        let path = self
+            .options
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("output.{}", kind.extension())));
+
+        // Top level expressions only exist to be evaluated by the JIT, so they're left out
+        let program = match self.codegen().program_module() {
+            Ok(program) => program,
+            Err(diagnostic) => return self.report(&diagnostic),
+        };
+        if self.options.executable {
+            if let Err(diagnostic) = self.codegen().add_entry_point(&program) {
+                return self.report(&diagnostic);
+            }
+        }
+
+        match kind {
+            EmitKind::Ll => program
+                .print_to_file(&path)
+                .map_err(|e| Error::new(ErrorKind::Other, e.to_string())),
+            EmitKind::Bc => {
+                if program.write_bitcode_to_path(&path) {
+                    Ok(())
+                } else {
+                    Err(Error::new(
+                        ErrorKind::Other,
+                        format!("Failed to write bitcode to {}", path.display()),
+                    ))
+                }
+            }
+            EmitKind::Obj | EmitKind::Asm => {
+                let target_machine = self.create_target_machine(&program)?;
+                let file_type = if kind == EmitKind::Obj {
+                    FileType::Object
+                } else {
+                    FileType::Assembly
+                };
+                target_machine
+                    .write_to_file(&program, file_type, &path)
+                    .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))
+            }
+        }
+    }
+
+    fn create_target_machine(&self, module: &Module<'_>) -> Result<TargetMachine, std::io::Error> {
+        Target::initialize_all(&InitializationConfig::default());
+
+        // Only tune for the host CPU when we're actually compiling for the host
+        let (triple, cpu, features) = match &self.options.target {
+            Some(triple) => (TargetTriple::create(triple), "generic".into(), "".into()),
+            None => (
+                TargetMachine::get_default_triple(),
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            ),
+        };
+
+        let target = Target::from_triple(&triple)
+            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+        let target_machine = target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features,
+                OptimizationLevel::Aggressive,
+                RelocMode::PIC,
+                CodeModel::Default,
+            )
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::Other,
+                    format!(
+                        "Unable to create a target machine for {}",
+                        triple.as_str().to_string_lossy()
+                    ),
+                )
+            })?;
+
+        module.set_triple(&triple);
+        module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+
+        Ok(target_machine)
+    }
+}
+
+// A module only has a single compile unit, so it's named after the first file
+pub(super) fn enable_debug_info(
+    codegen: &mut CodeGen<'_>,
+    options: &DriverOptions,
+    source_name: &str,
+) {
+    let file_name = match options.files.first() {
+        Some(file) => file.display().to_string(),
+        None => source_name.to_owned(),
+    };
+    codegen.enable_debug_info(&file_name, ".");
+}
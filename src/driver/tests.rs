@@ -0,0 +1,733 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::*;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+// Lets tests look at what the driver wrote after handing it ownership of the output
+#[derive(Clone, Default)]
+pub(super) struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedOutput {
+    pub(super) fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+fn make_driver<'ctx>(input: &'static str, context: &'ctx Context) -> (Driver<'ctx>, SharedOutput) {
+    let output = SharedOutput::default();
+    // Diagnostics and results both end up in the one output
+    let driver = Driver::new(
+        Box::new(input.as_bytes()),
+        Box::new(output.clone()),
+        context,
+    )
+    .with_error_output(Box::new(output.clone()));
+    (driver, output)
+}
+
+#[test]
+fn test_run_reports_every_error() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("foo(1);\n(1 + 2;\ndef bar(x) x;\n", &context);
+
+    driver.run().unwrap();
+
+    assert_eq!(driver.error_count(), 2);
+    let output = output.contents();
+    assert!(
+        output.contains("error[E0102]: Unknown function foo"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("error[E0001]: Expected ')', got ';'"),
+        "{}",
+        output
+    );
+    assert!(output.contains(" --> <stdin>:2:7"), "{}", output);
+}
+
+#[test]
+fn test_diagnostics_quote_the_whole_line() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("def (x) x + 1;\nnope(2) * 3;\n", &context);
+
+    driver.run().unwrap();
+
+    let output = output.contents();
+    assert!(output.contains("1 | def (x) x + 1;\n"), "{}", output);
+    assert!(output.contains("2 | nope(2) * 3;\n"), "{}", output);
+}
+
+#[test]
+fn test_error_inside_block_is_reported_once() {
+    let context = Context::create();
+    let input = "def f(x) { x + ; { 2; } 3 };\ndef g(x) { x; x * 2 };\n";
+    let (mut driver, output) = make_driver(input, &context);
+
+    driver.run().unwrap();
+
+    // The ';' after the error is inside the block, so recovery skips to the one after it
+    assert_eq!(driver.error_count(), 1, "{}", output.contents());
+    assert!(driver.symbols.contains_key("g"), "{}", output.contents());
+    assert_eq!(driver.call_function("g", &[4.0]), Ok(8.0));
+}
+
+#[test]
+fn test_top_level_expressions_leave_nothing_behind() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver(
+        "def f(x) x + 1;\nf(1);\nf(2);\nextern notreal();\nnotreal();\n",
+        &context,
+    );
+
+    driver.run().unwrap();
+
+    // Only f is remembered, none of the expressions that ran or couldn't
+    assert_eq!(driver.error_count(), 1, "{}", output.contents());
+    assert_eq!(driver.symbols.keys().collect::<Vec<_>>(), vec!["f"]);
+    assert_eq!(driver.missing_externs.len(), 1);
+    assert_eq!(driver.last_value(), Some(3.0));
+}
+
+#[test]
+fn test_chained_assignment() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver
+        .run_entry("def f() var x, y in (x = y = 1) + x + y;")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert_eq!(driver.call_function("f", &[]), Ok(3.0));
+}
+
+#[test]
+fn test_run_file_skips_prompt_and_shebang() {
+    let path = std::env::temp_dir().join(format!("kaleidoscope-{}.kal", std::process::id()));
+    std::fs::write(
+        &path,
+        "#!/usr/bin/env kaleidoscope\ndef double(x) x * 2;\ndouble(4);\n",
+    )
+    .unwrap();
+
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+    let result = driver.run_file(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(driver.error_count(), 0);
+    assert_eq!(output.contents(), "Evaluated to 8\n\n");
+}
+
+#[test]
+fn test_run_file_diagnostics_use_file_name() {
+    let path = std::env::temp_dir().join(format!("kaleidoscope-bad-{}.kal", std::process::id()));
+    std::fs::write(&path, "def (x) x;\n").unwrap();
+
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+    driver.run_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(driver.error_count(), 1);
+    assert!(output
+        .contents()
+        .contains(&format!(" --> {}:1:5", path.display())));
+}
+
+#[test]
+fn test_run_file_missing_file() {
+    let context = Context::create();
+    let (mut driver, _) = make_driver("", &context);
+
+    assert!(driver
+        .run_file(Path::new("definitely/not/a/file.kal"))
+        .is_err());
+}
+
+#[test]
+fn test_command_ops_lists_precedences() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver.handle_command(":ops").unwrap();
+
+    assert_eq!(
+        output.contents(),
+        "= 2\nor 4\nand 6\n!= 9\n== 9\n< 10\n<= 10\n> 10\n>= 10\n+ 20\n- 30\n% 40\n* 40\n/ 40\n^ 50\n"
+    );
+}
+
+#[test]
+fn test_command_funcs_lists_definitions_and_externs() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+    driver
+        .run_entry("extern sin(x);\ndef add(a b) a + b;\n")
+        .unwrap();
+
+    driver.handle_command(":funcs").unwrap();
+
+    assert_eq!(output.contents(), "extern sin(x)\ndef add(a b)\n");
+}
+
+#[test]
+fn test_command_ir_prints_function() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+    driver.run_entry("def one() 1;").unwrap();
+
+    driver.handle_command(":ir one").unwrap();
+    driver.handle_command(":ir two").unwrap();
+
+    let output = output.contents();
+    assert!(output.contains("define double @one()"), "{}", output);
+    assert!(output.contains("Unknown function two"), "{}", output);
+}
+
+#[test]
+fn test_command_ast_doesnt_run_expression() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver.handle_command(":ast foo(1)").unwrap();
+
+    let output = output.contents();
+    assert!(output.contains("callee: \"foo\""), "{}", output);
+    assert_eq!(driver.error_count(), 0);
+}
+
+#[test]
+fn test_command_set_toggles_options() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver.handle_command(":set print-ir on").unwrap();
+    assert!(driver.options.print_ir);
+    driver.handle_command(":set print-ir off").unwrap();
+    assert!(!driver.options.print_ir);
+
+    driver.handle_command(":set print-ir maybe").unwrap();
+    driver.handle_command(":set colour on").unwrap();
+    assert_eq!(
+        output.contents(),
+        "Expected on or off, got 'maybe'\nUnknown option colour\n"
+    );
+}
+
+#[test]
+fn test_command_reset_forgets_definitions() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+    driver.run_entry("def binary| 5 (a b) a;").unwrap();
+
+    driver.handle_command(":reset").unwrap();
+    driver.handle_command(":funcs").unwrap();
+
+    assert_eq!(output.contents(), "Session reset\n");
+    assert_eq!(driver.parser.environment.get_operator_precedence("|"), None);
+}
+
+#[test]
+fn test_unknown_command() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver.handle_command(":frobnicate").unwrap();
+
+    assert_eq!(
+        output.contents(),
+        "Unknown command :frobnicate, try :help to see what's available\n"
+    );
+}
+
+#[test]
+fn test_redefinition_replaces_body_for_callers() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver
+        .run_entry("def foo() 1;\ndef bar() foo();\ndef foo() 2;\n")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    let module = driver.codegen().program_module().unwrap();
+    let functions: Vec<String> = module
+        .get_functions()
+        .map(|function| function.get_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(functions, vec!["foo".to_string(), "bar".to_string()]);
+
+    let foo = module
+        .get_function("foo")
+        .unwrap()
+        .print_to_string()
+        .to_string();
+    assert!(foo.contains("ret double 2.000000e+00"), "{}", foo);
+    let bar = module
+        .get_function("bar")
+        .unwrap()
+        .print_to_string()
+        .to_string();
+    assert!(bar.contains("call double @foo()"), "{}", bar);
+}
+
+#[test]
+fn test_failed_redefinition_keeps_old_body() {
+    let context = Context::create();
+    let (mut driver, _) = make_driver("", &context);
+
+    driver
+        .run_entry("def foo(x) x + 1;\ndef foo(x) y;\n")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 1);
+    let module = driver.codegen().program_module().unwrap();
+    assert_eq!(module.get_functions().count(), 1);
+    let foo = module
+        .get_function("foo")
+        .unwrap()
+        .print_to_string()
+        .to_string();
+    assert!(foo.contains("fadd"), "{}", foo);
+}
+
+#[test]
+fn test_redefinition_can_change_arity_without_callers() {
+    let context = Context::create();
+    let (mut driver, _) = make_driver("", &context);
+
+    driver
+        .run_entry("def foo(x) x;\ndef foo(x y) if x < 1 then y else foo(x - 1, y);\n")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 0);
+    let module = driver.codegen().program_module().unwrap();
+    let foo = module.get_function("foo").unwrap();
+    assert_eq!(foo.count_params(), 2);
+}
+
+#[test]
+fn test_redefinition_cant_change_arity_with_callers() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver
+        .run_entry("def foo(x) x;\ndef bar() foo(1);\ndef foo(x y) x;\n")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 1);
+    assert!(
+        output.contents().contains("error[E0106]"),
+        "{}",
+        output.contents()
+    );
+    let module = driver.codegen().program_module().unwrap();
+    assert_eq!(module.get_function("foo").unwrap().count_params(), 1);
+}
+
+// Calls whatever the JIT currently has for `name`
+fn call_jitted(driver: &Driver, name: &str) -> f64 {
+    let symbol = &driver.symbols[name];
+    unsafe {
+        let function = driver
+            .engine
+            .as_ref()
+            .unwrap()
+            .get_function::<unsafe extern "C" fn() -> f64>(symbol)
+            .unwrap();
+        function.call()
+    }
+}
+
+#[test]
+fn test_jit_keeps_definitions_between_entries() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver.run_entry("def one() 1;").unwrap();
+    driver.run_entry("def two() one() + one();").unwrap();
+    driver.run_entry("two();").unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert_eq!(call_jitted(&driver, "two"), 2.0);
+}
+
+#[test]
+fn test_jit_callers_pick_up_redefinition_from_later_entry() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver.run_entry("def foo() 1;").unwrap();
+    driver.run_entry("def bar() foo() + 10;").unwrap();
+    assert_eq!(call_jitted(&driver, "bar"), 11.0);
+
+    driver.run_entry("def foo() 2;").unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert_eq!(call_jitted(&driver, "bar"), 12.0);
+}
+
+// Runs `script` the way the driver did before it kept a JIT around: every definition goes into
+// one module, and each top level expression gets a new engine for all of it
+fn run_recreating_engines(script: &str, context: &Context) {
+    let mut lexer = Lexer::new(script.as_bytes());
+    let mut parser = Parser::new();
+    let mut codegen = CodeGen::new(
+        context,
+        context.create_builder(),
+        context.create_module("baseline"),
+    );
+
+    loop {
+        lexer.get_next_token();
+        let is_anonymous = match lexer.current_token() {
+            Some(Token::EOF) | None => return,
+            Some(Token::Misc(';')) => continue,
+            Some(Token::Def) => false,
+            _ => true,
+        };
+        let expr = if is_anonymous {
+            parser.parse_top_level_expression(&mut lexer).unwrap()
+        } else {
+            parser.parse_function_definition(&mut lexer).unwrap()
+        };
+        let function = match &expr.kind {
+            ExprKind::Function { prototype, body } => {
+                codegen.codegen_function(prototype, body).unwrap()
+            }
+            _ => unreachable!(),
+        };
+        if !is_anonymous {
+            continue;
+        }
+
+        let engine = codegen
+            .module
+            .create_jit_execution_engine(OptimizationLevel::Aggressive)
+            .unwrap();
+        let name = function.get_name().to_string_lossy().into_owned();
+        unsafe {
+            engine
+                .get_function::<unsafe extern "C" fn() -> f64>(&name)
+                .unwrap()
+                .call();
+        }
+        engine.remove_module(&codegen.module).unwrap();
+    }
+}
+
+// Not a correctness test, run with `cargo test --release bench_ -- --ignored --nocapture`. That
+// an item only compiles new code is checked by
+// test_redefinition_cost_doesnt_scale_with_unrelated_definitions in codegen's tests
+#[test]
+#[ignore]
+fn bench_thousand_definitions() {
+    let mut script = String::from("def f0(x) x;\n");
+    for i in 1..1000 {
+        script.push_str(&format!("def f{}(x) f{}(x) + 1;\nf{}(0);\n", i, i - 1, i));
+    }
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    let start = std::time::Instant::now();
+    driver.run_entry(&script).unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+
+    let baseline_context = Context::create();
+    let start = std::time::Instant::now();
+    run_recreating_engines(&script, &baseline_context);
+    let baseline = start.elapsed();
+
+    println!(
+        "1000 definitions, each followed by a call, took {:?} with one JIT and {:?} with an \
+         engine per call ({:.1}x)",
+        elapsed,
+        baseline,
+        baseline.as_secs_f64() / elapsed.as_secs_f64()
+    );
+}
+
+#[test]
+fn test_extern_resolves_to_host_function() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+    driver.load_libraries().unwrap();
+
+    driver
+        .run_entry("extern cos(x);\ndef one() cos(0);\none();\n")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert_eq!(call_jitted(&driver, "one"), 1.0);
+}
+
+#[test]
+fn test_unresolved_extern_is_reported_instead_of_run() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+    driver.load_libraries().unwrap();
+
+    driver
+        .run_entry("extern notreal(x);\ndef f(x) notreal(x);\nf(1);\n")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 1);
+    assert!(
+        output
+            .contents()
+            .contains("error[E0109]: Unable to resolve external function notreal"),
+        "{}",
+        output.contents()
+    );
+}
+
+#[test]
+fn test_extern_can_forward_declare_a_definition() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver
+        .run_entry(indoc! {"
+            extern odd(x);
+            def even(x) if x < 1 then 1 else odd(x - 1);
+            def odd(x) if x < 1 then 0 else even(x - 1);
+            def fouriseven() even(4);
+        "})
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert_eq!(call_jitted(&driver, "fouriseven"), 1.0);
+}
+
+#[test]
+fn test_load_missing_library() {
+    let context = Context::create();
+    let (driver, _) = make_driver("", &context);
+    let driver = driver.with_options(DriverOptions {
+        load: vec![PathBuf::from("definitely/not/a/library.so")],
+        ..Default::default()
+    });
+
+    let error = driver.load_libraries().unwrap_err();
+    assert!(error.to_string().contains("library.so"), "{}", error);
+}
+
+#[test]
+fn test_extern_resolves_to_runtime_function() {
+    let context = Context::create();
+    let (mut driver, output) = make_driver("", &context);
+
+    driver
+        .run_entry("extern maxd(a b);\ndef biggest() maxd(3, 4);\n")
+        .unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert_eq!(call_jitted(&driver, "biggest"), 4.0);
+}
+
+// Emits `def double(x) x * 2` as `kind`, returning what emit did and the file it wrote
+fn emit_double(kind: EmitKind, target: Option<&str>) -> (Result<(), std::io::Error>, Vec<u8>) {
+    // Tests run in parallel, so each target gets a file of its own
+    let path = std::env::temp_dir().join(format!(
+        "kaleidoscope-emit-{}-{}.{}",
+        target.unwrap_or("host"),
+        std::process::id(),
+        kind.extension()
+    ));
+    let context = Context::create();
+    let (driver, output) = make_driver("", &context);
+    let mut driver = driver.with_options(DriverOptions {
+        emit: Some(kind),
+        output: Some(path.clone()),
+        target: target.map(String::from),
+        ..Default::default()
+    });
+
+    driver.run_entry("def double(x) x * 2;\n").unwrap();
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    let result = driver.emit();
+    let contents = std::fs::read(&path).unwrap_or_default();
+    std::fs::remove_file(&path).ok();
+    (result, contents)
+}
+
+#[test]
+fn test_emit_writes_each_kind() {
+    let (result, ir) = emit_double(EmitKind::Ll, None);
+    result.unwrap();
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(ir.contains("define double @double(double %x)"), "{}", ir);
+
+    // Bitcode starts with the 'BC' magic number
+    let (result, bitcode) = emit_double(EmitKind::Bc, None);
+    result.unwrap();
+    assert!(bitcode.starts_with(b"BC"));
+
+    let (result, object) = emit_double(EmitKind::Obj, None);
+    result.unwrap();
+    assert!(!object.is_empty());
+}
+
+#[test]
+fn test_emit_with_invalid_target_is_an_error() {
+    let (result, contents) = emit_double(EmitKind::Obj, Some("nonsense-target-triple"));
+
+    assert!(result.is_err());
+    assert!(contents.is_empty());
+}
+
+#[test]
+fn test_emit_executable_adds_entry_point() {
+    let path = std::env::temp_dir().join(format!("kaleidoscope-exe-{}.ll", std::process::id()));
+    let context = Context::create();
+    let (driver, output) = make_driver("", &context);
+    let mut driver = driver.with_options(DriverOptions {
+        emit: Some(EmitKind::Ll),
+        output: Some(path.clone()),
+        executable: true,
+        ..Default::default()
+    });
+
+    driver.run_entry("def main() 3;\nmain();\n").unwrap();
+    driver.emit().unwrap();
+    let ir = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert!(output
+        .contents()
+        .contains("warning[W0002]: Top level expression isn't run by an executable"));
+    assert!(driver.engine.is_none());
+    assert!(ir.contains("define i32 @main()"), "{}", ir);
+    assert!(ir.contains("define double @kaleidoscope_main()"), "{}", ir);
+}
+
+#[test]
+fn test_interp_backend_runs_without_the_jit() {
+    let context = Context::create();
+    let (driver, output) = make_driver("", &context);
+    let mut driver = driver.with_options(DriverOptions {
+        backend: Backend::Interp,
+        ..Default::default()
+    });
+
+    driver.run_entry("def foo() 1;").unwrap();
+    driver.run_entry("def bar() foo() + 10;").unwrap();
+    driver.run_entry("def foo() 2;").unwrap();
+    driver.run_entry("extern cos(x); cos(0);").unwrap();
+
+    assert_eq!(driver.error_count(), 0, "{}", output.contents());
+    assert!(driver.symbols.is_empty());
+    assert!(driver.codegen.is_none());
+    assert!(driver.engine.is_none());
+    let bar = driver
+        .interpreter
+        .call_function("bar", &[], Span::default());
+    assert_eq!(bar, Ok(12.0));
+}
+
+#[test]
+fn test_interp_backend_reports_errors() {
+    let context = Context::create();
+    let (driver, output) = make_driver("", &context);
+    let mut driver = driver.with_options(DriverOptions {
+        backend: Backend::Interp,
+        ..Default::default()
+    });
+
+    driver.run_entry("nope(1);").unwrap();
+
+    assert_eq!(driver.error_count(), 1);
+    assert!(output.contents().contains("Unknown function nope"));
+}
+
+#[test]
+fn test_both_backends_reject_loop_control_outside_loop_when_defined() {
+    for backend in [Backend::Jit, Backend::Interp] {
+        let context = Context::create();
+        let (driver, output) = make_driver("", &context);
+        let mut driver = driver.with_options(DriverOptions {
+            backend,
+            ..Default::default()
+        });
+
+        driver
+            .run_entry("def f(x) if x then break else 1;")
+            .unwrap();
+
+        assert_eq!(driver.error_count(), 1, "{:?}", backend);
+        assert_eq!(driver.errors()[0].code, code::OUTSIDE_LOOP);
+        assert!(output.contents().contains("1:20"), "{}", output.contents());
+    }
+}
+
+#[test]
+fn test_diagnostics_and_results_go_to_separate_outputs() {
+    let context = Context::create();
+    let (output, error_output) = (SharedOutput::default(), SharedOutput::default());
+    let mut driver = Driver::new(
+        Box::new("nope(1);\n1 + 2;\n".as_bytes()),
+        Box::new(output.clone()),
+        &context,
+    )
+    .with_error_output(Box::new(error_output.clone()))
+    .with_prompt(false);
+
+    driver.run().unwrap();
+
+    assert_eq!(output.contents(), "Evaluated to 3\n\n");
+    assert!(error_output
+        .contents()
+        .starts_with("error[E0102]: Unknown function nope"));
+}
+
+#[test]
+fn test_interp_backend_rejects_what_needs_generated_code() {
+    let path = std::env::temp_dir().join(format!("kaleidoscope-interp-{}.o", std::process::id()));
+    let context = Context::create();
+    let (driver, output) = make_driver("", &context);
+    let mut driver = driver.with_options(DriverOptions {
+        backend: Backend::Interp,
+        print_ir: true,
+        emit: Some(EmitKind::Obj),
+        output: Some(path.clone()),
+        ..Default::default()
+    });
+
+    driver.run_entry("def one() 1;").unwrap();
+    driver.dump_ir().unwrap();
+    driver.emit().unwrap();
+    driver.handle_command(":ir").unwrap();
+    driver.handle_command(":funcs").unwrap();
+
+    assert!(!path.exists());
+    let codes: Vec<&str> = driver.errors().iter().map(|error| error.code).collect();
+    assert_eq!(codes, [code::REQUIRES_JIT; 4]);
+    let output = output.contents();
+    assert!(
+        output.contains("--emit requires the jit backend"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(":funcs requires the jit backend"),
+        "{}",
+        output
+    );
+}
@@ -1,22 +1,54 @@
 use std::collections::HashMap;
 
+/// Which side a chain of operators with the same precedence groups from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`
+    Right,
+}
+
 #[derive(Debug)]
 pub struct Environment {
-    operator_precedence: HashMap<char, i32>,
+    operator_precedence: HashMap<String, i32>,
+    // Operators missing from here are left associative
+    operator_associativity: HashMap<String, Associativity>,
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
             operator_precedence: HashMap::new(),
+            operator_associativity: HashMap::new(),
         }
     }
-    pub fn get_operator_precedence(&self, operator: char) -> Option<i32> {
-        return self.operator_precedence.get(&operator).copied();
+    pub fn get_operator_precedence(&self, operator: &str) -> Option<i32> {
+        return self.operator_precedence.get(operator).copied();
+    }
+    pub fn get_operator_associativity(&self, operator: &str) -> Associativity {
+        self.operator_associativity
+            .get(operator)
+            .copied()
+            .unwrap_or(Associativity::Left)
     }
-    pub fn add_operator_precedence(&mut self, op_precedence_pair: (char, i32)) {
+    /// Every operator with its precedence, loosest binding first
+    pub fn operator_precedences(&self) -> Vec<(String, i32)> {
+        let mut precedences: Vec<(String, i32)> = self
+            .operator_precedence
+            .iter()
+            .map(|(operator, precedence)| (operator.clone(), *precedence))
+            .collect();
+        precedences.sort_by_key(|(operator, precedence)| (*precedence, operator.clone()));
+        precedences
+    }
+    pub fn add_operator_precedence(&mut self, op_precedence_pair: (&str, i32)) {
         self.operator_precedence
-            .insert(op_precedence_pair.0, op_precedence_pair.1);
+            .insert(op_precedence_pair.0.to_owned(), op_precedence_pair.1);
+    }
+    pub fn set_operator_associativity(&mut self, operator: &str, associativity: Associativity) {
+        self.operator_associativity
+            .insert(operator.to_owned(), associativity);
     }
 }
 
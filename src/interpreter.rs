@@ -0,0 +1,714 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use kaleidoscope_runtime::{RuntimeFnPointer, RUNTIME_FNS};
+
+use crate::ast::{Expr, ExprKind, ForVal, IfVal, VarVal, WhileVal};
+use crate::diagnostic::{code, Diagnostic};
+use crate::lexer::Span;
+
+/// Evaluates expressions by walking the tree, without going anywhere near LLVM. It's meant to
+/// behave exactly like the generated code does, so it doubles as a reference for codegen
+pub struct Interpreter {
+    functions: HashMap<String, Rc<Function>>,
+    // Argument names of every extern, which resolve to runtime or host functions when called
+    externs: HashMap<String, Vec<String>>,
+}
+
+struct Function {
+    args: Vec<String>,
+    body: Expr,
+    // Every function and operator the body calls, so a redefinition can tell if anything uses it
+    callees: HashSet<String>,
+}
+
+// Variables visible to the expression being evaluated
+type Scope = HashMap<String, f64>;
+
+// Why evaluation stopped before producing a value. break and continue unwind to the innermost
+// loop, the Checker has already made sure there is one
+enum Unwind {
+    Error(Diagnostic),
+    Break(Span),
+    Continue(Span),
+}
+
+impl From<Diagnostic> for Unwind {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Unwind::Error(diagnostic)
+    }
+}
+
+impl Unwind {
+    fn into_diagnostic(self) -> Diagnostic {
+        let (keyword, span) = match self {
+            Unwind::Error(diagnostic) => return diagnostic,
+            Unwind::Break(span) => ("break", span),
+            Unwind::Continue(span) => ("continue", span),
+        };
+        Diagnostic::error(
+            code::OUTSIDE_LOOP,
+            format!("'{}' outside of a loop", keyword),
+            span,
+        )
+    }
+}
+
+// The math functions externs can resolve to, a stand in for the host's libm
+#[derive(Clone, Copy)]
+enum HostFn {
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+}
+
+const HOST_FNS: [(&str, HostFn); 23] = [
+    ("sin", HostFn::Unary(f64::sin)),
+    ("cos", HostFn::Unary(f64::cos)),
+    ("tan", HostFn::Unary(f64::tan)),
+    ("asin", HostFn::Unary(f64::asin)),
+    ("acos", HostFn::Unary(f64::acos)),
+    ("atan", HostFn::Unary(f64::atan)),
+    ("sinh", HostFn::Unary(f64::sinh)),
+    ("cosh", HostFn::Unary(f64::cosh)),
+    ("tanh", HostFn::Unary(f64::tanh)),
+    ("exp", HostFn::Unary(f64::exp)),
+    ("log", HostFn::Unary(f64::ln)),
+    ("log2", HostFn::Unary(f64::log2)),
+    ("log10", HostFn::Unary(f64::log10)),
+    ("sqrt", HostFn::Unary(f64::sqrt)),
+    ("fabs", HostFn::Unary(f64::abs)),
+    ("floor", HostFn::Unary(f64::floor)),
+    ("ceil", HostFn::Unary(f64::ceil)),
+    ("round", HostFn::Unary(f64::round)),
+    ("atan2", HostFn::Binary(f64::atan2)),
+    ("pow", HostFn::Binary(f64::powf)),
+    ("fmod", HostFn::Binary(fmod)),
+    ("fmin", HostFn::Binary(f64::min)),
+    ("fmax", HostFn::Binary(f64::max)),
+];
+
+fn fmod(a: f64, b: f64) -> f64 {
+    a % b
+}
+
+// Operators eval_binary handles itself, anything else has to be user-defined
+const BUILTIN_BINARY_OPERATORS: [&str; 12] = [
+    "+", "-", "*", "/", "%", "^", "<", ">", "<=", ">=", "==", "!=",
+];
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            functions: HashMap::new(),
+            externs: HashMap::new(),
+        }
+    }
+
+    /// Makes a function callable, replacing any earlier definition of the same name
+    pub fn define_function(&mut self, prototype: &Expr, body: &Expr) -> Result<(), Diagnostic> {
+        let (name, args) = expect_prototype(prototype)?;
+
+        if let Some(existing_arg_count) = self.arg_count(name).filter(|count| *count != args.len())
+        {
+            let has_callers = self
+                .functions
+                .iter()
+                .any(|(caller, function)| caller != name && function.callees.contains(name));
+            if has_callers {
+                return Err(Diagnostic::error(
+                    code::REDEFINITION,
+                    format!(
+                        "Unable to redefine func {} with a different number of arguments",
+                        name
+                    ),
+                    prototype.span,
+                )
+                .with_note(format!(
+                    "{} is called with {} argument(s) elsewhere",
+                    name, existing_arg_count
+                )));
+            }
+        }
+
+        // Codegen rejects these when it generates the function, so don't wait for a call
+        let mut checker = Checker::new(self, Some((name, args.len())), args);
+        checker.check(body)?;
+        let callees = checker.callees;
+
+        self.functions.insert(
+            name.clone(),
+            Rc::new(Function {
+                args: args.clone(),
+                body: body.clone(),
+                callees,
+            }),
+        );
+        Ok(())
+    }
+
+    pub fn declare_extern(&mut self, prototype: &Expr) -> Result<(), Diagnostic> {
+        let (name, args) = expect_prototype(prototype)?;
+        self.externs.insert(name.clone(), args.clone());
+        Ok(())
+    }
+
+    /// Evaluates a top level expression, which can't see any variables
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<f64, Diagnostic> {
+        Checker::new(self, None, &[]).check(expr)?;
+        self.eval(expr, &mut Scope::new())
+            .map_err(Unwind::into_diagnostic)
+    }
+
+    pub fn call_function(
+        &mut self,
+        callee: &str,
+        args: &[f64],
+        span: Span,
+    ) -> Result<f64, Diagnostic> {
+        if let Some(function) = self.functions.get(callee).cloned() {
+            check_arity(callee, function.args.len(), args.len(), span)?;
+            let mut scope: Scope = function
+                .args
+                .iter()
+                .cloned()
+                .zip(args.iter().copied())
+                .collect();
+            return self
+                .eval(&function.body, &mut scope)
+                .map_err(Unwind::into_diagnostic);
+        }
+
+        let declared_args = self.externs.get(callee).ok_or_else(|| {
+            Diagnostic::error(
+                code::UNKNOWN_FUNCTION,
+                format!("Unknown function {}", callee),
+                span,
+            )
+        })?;
+        check_arity(callee, declared_args.len(), args.len(), span)?;
+
+        call_extern(callee, args).ok_or_else(|| {
+            Diagnostic::error(
+                code::UNRESOLVED_EXTERN,
+                format!("Unable to resolve external function {}", callee),
+                span,
+            )
+            .with_note("the interpreter only knows about runtime and math library functions")
+        })
+    }
+
+    // How many arguments a function or extern called `name` takes, if there is one
+    fn arg_count(&self, name: &str) -> Option<usize> {
+        self.functions
+            .get(name)
+            .map(|function| function.args.len())
+            .or_else(|| self.externs.get(name).map(Vec::len))
+    }
+
+    fn eval(&mut self, expr: &Expr, scope: &mut Scope) -> Result<f64, Unwind> {
+        match &expr.kind {
+            ExprKind::Number(num) => Ok(*num),
+
+            ExprKind::Variable { name } => scope.get(name).copied().ok_or_else(|| {
+                Diagnostic::error(
+                    code::UNKNOWN_VARIABLE,
+                    format!("Unknown variable name {}", name),
+                    expr.span,
+                )
+                .into()
+            }),
+
+            ExprKind::Binary { operator, lhs, rhs } => self.eval_binary(operator, lhs, rhs, scope),
+
+            ExprKind::Unary { operator, operand } => {
+                let operand = self.eval(operand, scope)?;
+                let operator_fn = format!("unary{}", operator);
+                if self.arg_count(&operator_fn).is_some() {
+                    Ok(self.call_function(&operator_fn, &[operand], expr.span)?)
+                } else if operator == "-" {
+                    Ok(-operand)
+                } else if operator == "not" {
+                    Ok(bool_to_f64(!is_true(operand)))
+                } else {
+                    Err(Diagnostic::error(
+                        code::UNKNOWN_OPERATOR,
+                        format!("Unknown unary operator {}", operator),
+                        expr.span,
+                    )
+                    .with_note(format!(
+                        "operators can be defined with `def unary{} (operand) ...`",
+                        operator
+                    ))
+                    .into())
+                }
+            }
+
+            ExprKind::Call { callee, args } => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, scope)?);
+                }
+                Ok(self.call_function(callee, &values, expr.span)?)
+            }
+
+            ExprKind::Prototype { .. } | ExprKind::Function { .. } => Err(Diagnostic::error(
+                code::INVALID_FUNCTION,
+                "Functions can only be defined at the top level",
+                expr.span,
+            )
+            .into()),
+
+            ExprKind::If(if_val) => self.eval_if(if_val, scope),
+
+            ExprKind::For(for_val) => self.eval_for(for_val, scope),
+
+            ExprKind::Var(var_val) => self.eval_var(var_val, scope),
+
+            ExprKind::While(while_val) => self.eval_while(while_val, scope),
+
+            ExprKind::Break => Err(Unwind::Break(expr.span)),
+
+            ExprKind::Continue => Err(Unwind::Continue(expr.span)),
+
+            ExprKind::Block(expressions) => {
+                // Same as codegen, an empty block evaluates to 0.0
+                let mut value = 0.0;
+                for expression in expressions {
+                    value = self.eval(expression, scope)?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    fn eval_binary(
+        &mut self,
+        op: &str,
+        lhs: &Expr,
+        rhs: &Expr,
+        scope: &mut Scope,
+    ) -> Result<f64, Unwind> {
+        // Assignment doesn't evaluate its lhs, so it has to be handled before anything else
+        if op == "=" {
+            let var_name = match &lhs.kind {
+                ExprKind::Variable { name } => name,
+                _ => {
+                    return Err(Diagnostic::error(
+                        code::INVALID_ASSIGNMENT,
+                        "Destination of '=' must be a variable",
+                        lhs.span,
+                    )
+                    .into())
+                }
+            };
+            let value = self.eval(rhs, scope)?;
+            return match scope.get_mut(var_name) {
+                Some(variable) => {
+                    *variable = value;
+                    Ok(value)
+                }
+                None => Err(Diagnostic::error(
+                    code::UNKNOWN_VARIABLE,
+                    format!("Unknown variable name {}", var_name),
+                    lhs.span,
+                )
+                .into()),
+            };
+        }
+
+        // The logical operators only evaluate their rhs when the lhs doesn't decide the result
+        if op == "and" || op == "or" {
+            let lhs = is_true(self.eval(lhs, scope)?);
+            if lhs == (op == "or") {
+                return Ok(bool_to_f64(lhs));
+            }
+            return Ok(bool_to_f64(is_true(self.eval(rhs, scope)?)));
+        }
+
+        let span = lhs.span.to(rhs.span);
+        let lhs = self.eval(lhs, scope)?;
+        let rhs = self.eval(rhs, scope)?;
+
+        match op {
+            "+" => Ok(lhs + rhs),
+            "-" => Ok(lhs - rhs),
+            "*" => Ok(lhs * rhs),
+            "/" => Ok(lhs / rhs),
+            // Rust's remainder is fmod, the same as LLVM's frem
+            "%" => Ok(lhs % rhs),
+            "^" => Ok(lhs.powf(rhs)),
+            // Ordered like codegen's, apart from != which holds whenever == doesn't
+            "<" => Ok(bool_to_f64(lhs < rhs)),
+            ">" => Ok(bool_to_f64(lhs > rhs)),
+            "<=" => Ok(bool_to_f64(lhs <= rhs)),
+            ">=" => Ok(bool_to_f64(lhs >= rhs)),
+            "==" => Ok(bool_to_f64(lhs == rhs)),
+            "!=" => Ok(bool_to_f64(lhs != rhs)),
+            _ => {
+                let operator_fn = format!("binary{}", op);
+                if self.arg_count(&operator_fn).is_some() {
+                    Ok(self.call_function(&operator_fn, &[lhs, rhs], span)?)
+                } else {
+                    Err(Diagnostic::error(
+                        code::UNKNOWN_OPERATOR,
+                        format!("Unknown binary operator {}", op),
+                        span,
+                    )
+                    .with_note(format!(
+                        "operators can be defined with `def binary{} (lhs rhs) ...`",
+                        op
+                    ))
+                    .into())
+                }
+            }
+        }
+    }
+
+    fn eval_if(&mut self, if_val: &IfVal, scope: &mut Scope) -> Result<f64, Unwind> {
+        let condition = self.eval(&if_val.if_boolish_test, scope)?;
+        if is_true(condition) {
+            self.eval(&if_val.then, scope)
+        } else {
+            self.eval(&if_val.elves, scope)
+        }
+    }
+
+    fn eval_for(&mut self, for_val: &ForVal, scope: &mut Scope) -> Result<f64, Unwind> {
+        let start = self.eval(&for_val.start, scope)?;
+        // The loop variable shadows any existing variable of the same name while in the body
+        let shadowed_value = scope.insert(for_val.var_name.clone(), start);
+
+        let result = loop {
+            match self.eval_for_iteration(for_val, scope) {
+                Ok(true) => (),
+                // A for expression always evaluates to 0.0
+                Ok(false) => break Ok(0.0),
+                Err(unwind) => break Err(unwind),
+            }
+        };
+
+        restore(scope, &for_val.var_name, shadowed_value);
+        result
+    }
+
+    // Runs the body once and steps the variable, returning whether to go around again. This is
+    // the same order as the generated loop, so the end condition sees the variable before it's
+    // stepped
+    fn eval_for_iteration(&mut self, for_val: &ForVal, scope: &mut Scope) -> Result<bool, Unwind> {
+        if !self.eval_loop_body(&for_val.body, scope)? {
+            return Ok(false);
+        }
+        let step = match &for_val.step {
+            Some(step) => self.eval(step, scope)?,
+            None => 1.0,
+        };
+        let end = self.eval(&for_val.end, scope)?;
+
+        if let Some(variable) = scope.get_mut(&for_val.var_name) {
+            *variable += step;
+        }
+        Ok(is_true(end))
+    }
+
+    fn eval_while(&mut self, while_val: &WhileVal, scope: &mut Scope) -> Result<f64, Unwind> {
+        while is_true(self.eval(&while_val.condition, scope)?) {
+            if !self.eval_loop_body(&while_val.body, scope)? {
+                break;
+            }
+        }
+        // Like a for expression, a while expression always evaluates to 0.0
+        Ok(0.0)
+    }
+
+    // Runs a loop body, returning false if it broke out of the loop
+    fn eval_loop_body(&mut self, body: &Expr, scope: &mut Scope) -> Result<bool, Unwind> {
+        match self.eval(body, scope) {
+            Ok(_) | Err(Unwind::Continue(_)) => Ok(true),
+            Err(Unwind::Break(_)) => Ok(false),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    fn eval_var(&mut self, var_val: &VarVal, scope: &mut Scope) -> Result<f64, Unwind> {
+        let mut shadowed_values = Vec::with_capacity(var_val.bindings.len());
+        let mut result = Ok(0.0);
+
+        for (var_name, initializer) in &var_val.bindings {
+            // Evaluate the initializer before binding the name, so `var a = a in ...` refers to
+            // the outer `a`
+            let initial_value = match initializer {
+                Some(initializer) => self.eval(initializer, scope),
+                None => Ok(0.0),
+            };
+            match initial_value {
+                Ok(value) => {
+                    shadowed_values.push((var_name, scope.insert(var_name.clone(), value)))
+                }
+                Err(unwind) => {
+                    result = Err(unwind);
+                    break;
+                }
+            }
+        }
+
+        if result.is_ok() {
+            result = self.eval(&var_val.body, scope);
+        }
+
+        // Restore everything the bindings shadowed, newest first in case a name was bound twice
+        for (var_name, shadowed_value) in shadowed_values.into_iter().rev() {
+            restore(scope, var_name, shadowed_value);
+        }
+        result
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn expect_prototype(prototype: &Expr) -> Result<(&String, &Vec<String>), Diagnostic> {
+    match &prototype.kind {
+        ExprKind::Prototype { name, args } => Ok((name, args)),
+        _ => Err(Diagnostic::error(
+            code::INVALID_FUNCTION,
+            "Expected a function prototype",
+            prototype.span,
+        )),
+    }
+}
+
+// Finds everything codegen rejects while generating a function, in the order codegen finds it,
+// so both backends report the same error as soon as a definition is entered
+struct Checker<'a> {
+    interpreter: &'a Interpreter,
+    // The function being defined, which its body can already call
+    defining: Option<(&'a str, usize)>,
+    variables: Vec<&'a str>,
+    // Loop conditions, steps and the like aren't part of the body
+    in_loop: bool,
+    callees: HashSet<String>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(
+        interpreter: &'a Interpreter,
+        defining: Option<(&'a str, usize)>,
+        args: &'a [String],
+    ) -> Self {
+        Checker {
+            interpreter,
+            defining,
+            variables: args.iter().map(String::as_str).collect(),
+            in_loop: false,
+            callees: HashSet::new(),
+        }
+    }
+
+    fn arg_count(&self, name: &str) -> Option<usize> {
+        match self.defining {
+            Some((defining, arg_count)) if defining == name => Some(arg_count),
+            _ => self.interpreter.arg_count(name),
+        }
+    }
+
+    // Looks up a function that's about to be called, remembering the call
+    fn callee(&mut self, name: &str) -> Option<usize> {
+        let arg_count = self.arg_count(name)?;
+        self.callees.insert(name.to_owned());
+        Some(arg_count)
+    }
+
+    fn check_variable(&self, name: &str, span: Span) -> Result<(), Diagnostic> {
+        if self.variables.contains(&name) {
+            return Ok(());
+        }
+        Err(Diagnostic::error(
+            code::UNKNOWN_VARIABLE,
+            format!("Unknown variable name {}", name),
+            span,
+        ))
+    }
+
+    fn check(&mut self, expr: &'a Expr) -> Result<(), Diagnostic> {
+        match &expr.kind {
+            ExprKind::Number(_) | ExprKind::Prototype { .. } | ExprKind::Function { .. } => Ok(()),
+            ExprKind::Variable { name } => self.check_variable(name, expr.span),
+            ExprKind::Binary { operator, lhs, rhs } => self.check_binary(operator, lhs, rhs),
+            ExprKind::Unary { operator, operand } => {
+                self.check(operand)?;
+                let operator_fn = format!("unary{}", operator);
+                if self.callee(&operator_fn).is_some() || operator == "-" || operator == "not" {
+                    return Ok(());
+                }
+                Err(Diagnostic::error(
+                    code::UNKNOWN_OPERATOR,
+                    format!("Unknown unary operator {}", operator),
+                    expr.span,
+                )
+                .with_note(format!(
+                    "operators can be defined with `def unary{} (operand) ...`",
+                    operator
+                )))
+            }
+            ExprKind::Call { callee, args } => {
+                let arg_count = self.callee(callee).ok_or_else(|| {
+                    Diagnostic::error(
+                        code::UNKNOWN_FUNCTION,
+                        format!("Unknown function {}", callee),
+                        expr.span,
+                    )
+                })?;
+                check_arity(callee, arg_count, args.len(), expr.span)?;
+                args.iter().try_for_each(|arg| self.check(arg))
+            }
+            ExprKind::If(if_val) => {
+                self.check(&if_val.if_boolish_test)?;
+                self.check(&if_val.then)?;
+                self.check(&if_val.elves)
+            }
+            ExprKind::For(for_val) => {
+                self.check(&for_val.start)?;
+                self.variables.push(&for_val.var_name);
+                self.check_loop_body(&for_val.body)?;
+                if let Some(step) = &for_val.step {
+                    self.check(step)?;
+                }
+                self.check(&for_val.end)?;
+                self.variables.pop();
+                Ok(())
+            }
+            ExprKind::Var(var_val) => {
+                let outer_variables = self.variables.len();
+                for (var_name, initializer) in &var_val.bindings {
+                    if let Some(initializer) = initializer {
+                        self.check(initializer)?;
+                    }
+                    self.variables.push(var_name);
+                }
+                self.check(&var_val.body)?;
+                self.variables.truncate(outer_variables);
+                Ok(())
+            }
+            ExprKind::While(while_val) => {
+                self.check(&while_val.condition)?;
+                self.check_loop_body(&while_val.body)
+            }
+            ExprKind::Break if !self.in_loop => Err(Unwind::Break(expr.span).into_diagnostic()),
+            ExprKind::Continue if !self.in_loop => {
+                Err(Unwind::Continue(expr.span).into_diagnostic())
+            }
+            ExprKind::Break | ExprKind::Continue => Ok(()),
+            ExprKind::Block(expressions) => expressions
+                .iter()
+                .try_for_each(|expression| self.check(expression)),
+        }
+    }
+
+    fn check_binary(&mut self, op: &str, lhs: &'a Expr, rhs: &'a Expr) -> Result<(), Diagnostic> {
+        if op == "=" {
+            let var_name = match &lhs.kind {
+                ExprKind::Variable { name } => name,
+                _ => {
+                    return Err(Diagnostic::error(
+                        code::INVALID_ASSIGNMENT,
+                        "Destination of '=' must be a variable",
+                        lhs.span,
+                    ))
+                }
+            };
+            self.check(rhs)?;
+            return self.check_variable(var_name, lhs.span);
+        }
+
+        self.check(lhs)?;
+        self.check(rhs)?;
+        if op == "and" || op == "or" || BUILTIN_BINARY_OPERATORS.contains(&op) {
+            return Ok(());
+        }
+        if self.callee(&format!("binary{}", op)).is_some() {
+            return Ok(());
+        }
+        Err(Diagnostic::error(
+            code::UNKNOWN_OPERATOR,
+            format!("Unknown binary operator {}", op),
+            lhs.span.to(rhs.span),
+        )
+        .with_note(format!(
+            "operators can be defined with `def binary{} (lhs rhs) ...`",
+            op
+        )))
+    }
+
+    fn check_loop_body(&mut self, body: &'a Expr) -> Result<(), Diagnostic> {
+        let in_loop = std::mem::replace(&mut self.in_loop, true);
+        let result = self.check(body);
+        self.in_loop = in_loop;
+        result
+    }
+}
+
+fn check_arity(callee: &str, expected: usize, given: usize, span: Span) -> Result<(), Diagnostic> {
+    if expected == given {
+        return Ok(());
+    }
+    Err(Diagnostic::error(
+        code::ARITY_MISMATCH,
+        format!("Incorrect number of arguments passed to {}", callee),
+        span,
+    )
+    .with_note(format!(
+        "{} takes {} argument(s), but {} were given",
+        callee, expected, given
+    )))
+}
+
+// Runtime functions win over the host's, the same way they do for the JIT
+fn call_extern(name: &str, args: &[f64]) -> Option<f64> {
+    let runtime_fn = RUNTIME_FNS
+        .iter()
+        .find(|func| func.name.to_bytes() == name.as_bytes())
+        .map(|func| func.func_pointer);
+    if let Some(runtime_fn) = runtime_fn {
+        return match (runtime_fn, args) {
+            (RuntimeFnPointer::Nullary(func), []) => Some(func()),
+            (RuntimeFnPointer::Unary(func), [x]) => Some(func(*x)),
+            (RuntimeFnPointer::Binary(func), [a, b]) => Some(func(*a, *b)),
+            _ => None,
+        };
+    }
+
+    let (_, host_fn) = HOST_FNS.iter().find(|(host_name, _)| *host_name == name)?;
+    match (host_fn, args) {
+        (HostFn::Unary(func), [x]) => Some(func(*x)),
+        (HostFn::Binary(func), [a, b]) => Some(func(*a, *b)),
+        _ => None,
+    }
+}
+
+// Conditions compare with ONE, so NaN counts as false
+fn is_true(value: f64) -> bool {
+    value != 0.0 && !value.is_nan()
+}
+
+fn bool_to_f64(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn restore(scope: &mut Scope, var_name: &str, shadowed_value: Option<f64>) {
+    match shadowed_value {
+        Some(value) => scope.insert(var_name.to_owned(), value),
+        None => scope.remove(var_name),
+    };
+}
+
+#[cfg(test)]
+mod tests;
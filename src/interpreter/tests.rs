@@ -0,0 +1,312 @@
+use super::*;
+use crate::lexer::{Lex, Lexer, Token};
+use crate::parser::{Parse, Parser};
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+// Runs every item in `source`, evaluating to whatever the last top level expression did
+fn run(source: &str) -> Result<f64, Diagnostic> {
+    let mut lexer = Lexer::new(source.as_bytes());
+    let mut parser = Parser::new();
+    let mut interpreter = Interpreter::new();
+    let mut result = 0.0;
+
+    loop {
+        lexer.get_next_token();
+        match lexer.current_token() {
+            Some(Token::EOF) | None => return Ok(result),
+            Some(Token::Misc(';')) => (),
+            Some(Token::Def) => {
+                let expr = parser.parse_function_definition(&mut lexer)?;
+                if let ExprKind::Function { prototype, body } = &expr.kind {
+                    interpreter.define_function(prototype, body)?;
+                }
+            }
+            Some(Token::Extern) => interpreter.declare_extern(&parser.parse_extern(&mut lexer)?)?,
+            _ => {
+                let expr = parser.parse_top_level_expression(&mut lexer)?;
+                if let ExprKind::Function { body, .. } = &expr.kind {
+                    result = interpreter.evaluate(body)?;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_interpret_arithmetic() {
+    assert_eq!(run("1 + 2 * 3 - 4;"), Ok(3.0));
+    assert_eq!(run("-(2 * 3);"), Ok(-6.0));
+    assert_eq!(run("2 < 3;"), Ok(1.0));
+    assert_eq!(run("3 < 2;"), Ok(0.0));
+    assert_eq!(run("7 / 2;"), Ok(3.5));
+    assert_eq!(run("-7 % 2;"), Ok(-1.0));
+    assert_eq!(run("2 ^ 3 ^ 2;"), Ok(512.0));
+}
+
+#[test]
+fn test_interpret_logical_operators() {
+    assert_eq!(run("2 and 3;"), Ok(1.0));
+    assert_eq!(run("2 and 0;"), Ok(0.0));
+    assert_eq!(run("0 or 0;"), Ok(0.0));
+    assert_eq!(run("0 or -1;"), Ok(1.0));
+    assert_eq!(run("not 0;"), Ok(1.0));
+    assert_eq!(run("not 2;"), Ok(0.0));
+    // NaN is false, like it is in conditions
+    assert_eq!(run("not (0 / 0);"), Ok(1.0));
+
+    // The rhs only runs when the lhs doesn't already decide the result
+    let source = indoc!(
+        "
+        def hits(x) var hit = 0 in (x and (hit = 1)) * 0 + (x or (hit = hit + 2)) * 0 + hit;
+        hits(0) * 10 + hits(1);
+        "
+    );
+    assert_eq!(run(source), Ok(21.0));
+}
+
+#[test]
+fn test_interpret_comparisons() {
+    assert_eq!(run("3 > 2;"), Ok(1.0));
+    assert_eq!(run("2 > 2;"), Ok(0.0));
+    assert_eq!(run("2 <= 2;"), Ok(1.0));
+    assert_eq!(run("3 <= 2;"), Ok(0.0));
+    assert_eq!(run("2 >= 3;"), Ok(0.0));
+    assert_eq!(run("1 + 1 == 2;"), Ok(1.0));
+    assert_eq!(run("1 != 1;"), Ok(0.0));
+    assert_eq!(run("1 < 2 == 2 < 3;"), Ok(1.0));
+}
+
+#[test]
+fn test_interpret_comparisons_with_nan() {
+    // Only != holds when either side is NaN, the same as codegen
+    for (op, expected) in [
+        ("<", 0.0),
+        (">", 0.0),
+        ("<=", 0.0),
+        (">=", 0.0),
+        ("==", 0.0),
+        ("!=", 1.0),
+    ] {
+        let lhs_nan = format!("(0 / 0) {} 1;", op);
+        let rhs_nan = format!("1 {} (0 / 0);", op);
+        assert_eq!(run(&lhs_nan), Ok(expected), "{}", lhs_nan);
+        assert_eq!(run(&rhs_nan), Ok(expected), "{}", rhs_nan);
+    }
+}
+
+#[test]
+fn test_interpret_recursive_function() {
+    let source = indoc! {"
+        def fib(n) if n < 2 then n else fib(n - 1) + fib(n - 2);
+        fib(10);
+    "};
+
+    assert_eq!(run(source), Ok(55.0));
+}
+
+#[test]
+fn test_interpret_redefinition() {
+    let source = indoc! {"
+        def foo() 1;
+        def bar() foo() + 10;
+        def foo() 2;
+        bar();
+    "};
+
+    assert_eq!(run(source), Ok(12.0));
+}
+
+#[test]
+fn test_interpret_for_checks_end_after_body() {
+    // Like the generated loop, the body runs once more than a C style loop would
+    let source = indoc! {"
+        def count(n) var total = 0 in (for i = 0, i < n in total = total + 1) + total;
+        count(3);
+    "};
+
+    assert_eq!(run(source), Ok(4.0));
+}
+
+#[test]
+fn test_interpret_var_restores_shadowed_variable() {
+    assert_eq!(run("def f(a) (var a = 2 in a) + a;\nf(1);"), Ok(3.0));
+    assert_eq!(run("def f(a) var a = a + 1, b = a in b;\nf(1);"), Ok(2.0));
+}
+
+#[test]
+fn test_interpret_chained_assignment() {
+    assert_eq!(
+        run("def f() var x, y in (x = y = 1) + x + y;\nf();"),
+        Ok(3.0)
+    );
+}
+
+#[test]
+fn test_interpret_user_defined_operators() {
+    let source = indoc! {"
+        def unary!(v) if v then 0 else 1;
+        def binary| 5 (a b) if a then 1 else if b then 1 else 0;
+        !0 + (0 | 1);
+    "};
+
+    assert_eq!(run(source), Ok(2.0));
+}
+
+#[test]
+fn test_interpret_externs() {
+    assert_eq!(run("extern cos(x);\ncos(0);"), Ok(1.0));
+    assert_eq!(run("extern maxd(a b);\nmaxd(1, 2);"), Ok(2.0));
+
+    let result = run("extern nope(x);\nnope(1);");
+    assert_eq!(result.unwrap_err().code, code::UNRESOLVED_EXTERN);
+}
+
+#[test]
+fn test_interpret_while() {
+    let source = indoc!(
+        "
+        def sumbelow(n) var i = 0, total = 0 in
+          (while i < n do total = total + (i = i + 1)) + total;
+        sumbelow(4);
+        "
+    );
+    assert_eq!(run(source), Ok(10.0));
+    // The condition is checked before the first iteration
+    assert_eq!(run("def f(x) (while 0 do x = 1) + x;\nf(5);"), Ok(5.0));
+}
+
+#[test]
+fn test_interpret_break_and_continue() {
+    // Finds the first multiple of 7 above n
+    let source = indoc!(
+        "
+        def search(n) var i = n in (while 1 do if (i = i + 1) % 7 == 0 then break else 0) + i;
+        search(10);
+        "
+    );
+    assert_eq!(run(source), Ok(14.0));
+
+    // continue still steps a for loop's variable, and break only leaves the innermost loop
+    let source = indoc!(
+        "
+        def oddsum(n) var total = 0 in
+          (for i = 0, i < n in if i % 2 == 0 then continue else total = total + i) + total;
+        def nested() var count = 0 in
+          (for i = 0, i < 3 in for j = 0, j < 10 in if j == 2 then break else count = count + 1)
+          + count;
+        oddsum(6) * 100 + nested();
+        "
+    );
+    assert_eq!(run(source), Ok(908.0));
+}
+
+#[test]
+fn test_interpret_loop_control_outside_loop() {
+    let result = run("def f(x) if x then break else 1;\nf(1);").unwrap_err();
+    assert_eq!(result.code, code::OUTSIDE_LOOP);
+    assert_eq!(result.message, "'break' outside of a loop");
+
+    // A loop in the caller doesn't count
+    let source = "def f() continue;\nfor i = 0, i < 1 in f();";
+    assert_eq!(run(source).unwrap_err().code, code::OUTSIDE_LOOP);
+    // Neither does a loop condition, which is outside the loop body
+    assert_eq!(
+        run("while break do 1;").unwrap_err().code,
+        code::OUTSIDE_LOOP
+    );
+}
+
+#[test]
+fn test_interpret_loop_control_outside_loop_is_rejected_when_defined() {
+    // Never called, and the branch with continue would never run
+    let result = run("def f(x) if 0 then continue else x;").unwrap_err();
+    assert_eq!(result.code, code::OUTSIDE_LOOP);
+    assert_eq!(result.message, "'continue' outside of a loop");
+
+    let result = run("def g(x) while x do break;\ndef h(x) (while x do 1) + break;").unwrap_err();
+    assert_eq!(result.message, "'break' outside of a loop");
+    assert_eq!(
+        run("if 0 then break else 2;").unwrap_err().code,
+        code::OUTSIDE_LOOP
+    );
+}
+
+#[test]
+fn test_interpret_blocks() {
+    assert_eq!(run("{ 1; 2; 3 };"), Ok(3.0));
+    let source = indoc!(
+        "
+        def count(n) {
+          var i = 0, steps = 0 in {
+            while i < n do {
+              i = i + 2;
+              steps = steps + 1;
+            };
+            steps
+          }
+        };
+        count(7);
+        "
+    );
+    assert_eq!(run(source), Ok(4.0));
+
+    // The parser never makes an empty block, but one built by hand still has a value
+    let empty = Expr {
+        kind: ExprKind::Block(vec![]),
+        span: Span::default(),
+    };
+    assert_eq!(Interpreter::new().evaluate(&empty), Ok(0.0));
+}
+
+#[test]
+fn test_interpret_errors() {
+    assert_eq!(
+        run("def f(x) y;\nf(1);").unwrap_err().code,
+        code::UNKNOWN_VARIABLE
+    );
+    assert_eq!(run("g(1);").unwrap_err().code, code::UNKNOWN_FUNCTION);
+    assert_eq!(
+        run("def f(x) x;\nf(1, 2);").unwrap_err().code,
+        code::ARITY_MISMATCH
+    );
+}
+
+#[test]
+fn test_interpret_errors_are_reported_when_defined() {
+    // None of these are ever called
+    let result = run("def f(x) x + y;").unwrap_err();
+    assert_eq!(result.code, code::UNKNOWN_VARIABLE);
+    assert_eq!(
+        run("def f(x) g(x);").unwrap_err().code,
+        code::UNKNOWN_FUNCTION
+    );
+    assert_eq!(
+        run("def f(x) x;\ndef g(x) f(x, x);").unwrap_err().code,
+        code::ARITY_MISMATCH
+    );
+    assert_eq!(
+        run("def f(x) !x;").unwrap_err().code,
+        code::UNKNOWN_OPERATOR
+    );
+    assert_eq!(
+        run("def f(x) x;\ndef g(x) f(x);\ndef f(a b) a;")
+            .unwrap_err()
+            .code,
+        code::REDEFINITION
+    );
+
+    // Variables are only in scope where codegen would have them
+    assert_eq!(
+        run("def f(x) (var y = 1 in y) + y;").unwrap_err().code,
+        code::UNKNOWN_VARIABLE
+    );
+    assert_eq!(
+        run("def f(n) for i = i, i < n in 1;").unwrap_err().code,
+        code::UNKNOWN_VARIABLE
+    );
+
+    // Recursion and functions without callers can still change their arguments
+    assert_eq!(run("def f(x) if x then f(x - 1) else 5;\nf(3);"), Ok(5.0));
+    assert_eq!(run("def f(x) x;\ndef f(a b) a + b;\nf(1, 2);"), Ok(3.0));
+}
@@ -1,4 +1,6 @@
 use std::{
+    collections::VecDeque,
+    fmt,
     io::{ErrorKind, Read},
     string::String,
 };
@@ -14,15 +16,90 @@ pub enum Token {
     If,
     Then,
     Else,
+    For,
+    In,
+    Binary,
+    Unary,
+    Var,
+    While,
+    Do,
+    Break,
+    Continue,
+    // Operators spelled with more than one character, like <= and the logical keywords. Single
+    // characters are all Misc
+    Operator(String),
     Misc(char),
 }
 
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::EOF => write!(f, "end of input"),
+            Token::Def => write!(f, "'def'"),
+            Token::Extern => write!(f, "'extern'"),
+            Token::Identifier(ident) => write!(f, "identifier '{}'", ident),
+            Token::Number(num) => write!(f, "number {}", num),
+            Token::If => write!(f, "'if'"),
+            Token::Then => write!(f, "'then'"),
+            Token::Else => write!(f, "'else'"),
+            Token::For => write!(f, "'for'"),
+            Token::In => write!(f, "'in'"),
+            Token::Binary => write!(f, "'binary'"),
+            Token::Unary => write!(f, "'unary'"),
+            Token::Var => write!(f, "'var'"),
+            Token::While => write!(f, "'while'"),
+            Token::Do => write!(f, "'do'"),
+            Token::Break => write!(f, "'break'"),
+            Token::Continue => write!(f, "'continue'"),
+            Token::Operator(op) => write!(f, "'{}'", op),
+            Token::Misc(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+/// A position in the source being lexed, as a 0-based byte offset and a 1-based line and column
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct SourceLocation {
+    pub offset: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The region of source a token or expression came from, `end` is exclusive
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub start: SourceLocation,
+    pub end: SourceLocation,
+}
+
+impl Span {
+    /// A span covering everything from the start of this span to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
 pub trait Lex {
     type Reader: Read;
 
     fn new(reader: Self::Reader) -> Self;
     fn get_next_token(&mut self) -> &Option<Token>;
     fn current_token(&self) -> &Option<Token>;
+    fn current_span(&self) -> Span;
+    /// Everything read so far, so diagnostics can quote the source they point at
+    fn source(&self) -> &str;
+    /// Reads ahead to the end of the current line so `source` holds all of it, the characters
+    /// read are still lexed as usual afterwards
+    fn read_rest_of_line(&mut self);
 }
 
 pub struct Lexer<T>
@@ -33,6 +110,14 @@ where
     buffer: Option<Token>,
     char_buffer: Option<char>,
     byte_buffer: [u8; 1],
+    // Location of the character in char_buffer, or of the end of input once it's been reached
+    char_location: SourceLocation,
+    // Location the next character read will have
+    next_location: SourceLocation,
+    token_span: Span,
+    source: String,
+    // Bytes read by read_rest_of_line that haven't been lexed yet
+    lookahead: VecDeque<u8>,
 }
 
 // Public Interface
@@ -49,17 +134,43 @@ where
             buffer: None,
             char_buffer: None,
             byte_buffer: [0],
+            char_location: SourceLocation::default(),
+            next_location: SourceLocation {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+            token_span: Span::default(),
+            source: String::new(),
+            lookahead: VecDeque::new(),
         }
     }
 
     fn get_next_token(&mut self) -> &Option<Token> {
         self.buffer = self.get_token();
+        // Lexing a token always reads one character past its end
+        self.token_span.end = self.char_location;
         &self.buffer
     }
 
     fn current_token(&self) -> &Option<Token> {
         &self.buffer
     }
+
+    fn current_span(&self) -> Span {
+        self.token_span
+    }
+
+    fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn read_rest_of_line(&mut self) {
+        if self.source.ends_with('\n') || self.lookahead.back() == Some(&b'\n') {
+            return;
+        }
+        self.read_ahead_to_newline();
+    }
 }
 
 // Private methods
@@ -93,6 +204,35 @@ where
 
     // Methods
 
+    fn read_ahead_to_newline(&mut self) {
+        while read_exact!(self.reader, self.byte_buffer).is_ok() {
+            let byte = self.byte_buffer[0];
+            self.lookahead.push_back(byte);
+            // Lexing exits on anything else once it gets there
+            if !byte.is_ascii() {
+                return;
+            }
+            self.source.push(char::from(byte));
+            if byte == b'\n' {
+                return;
+            }
+        }
+    }
+
+    fn advance_location(&mut self, c: Option<char>) {
+        self.char_location = self.next_location;
+        match c {
+            Some('\n') => {
+                self.next_location.line += 1;
+                self.next_location.column = 1;
+            }
+            Some(_) => self.next_location.column += 1,
+            // Stay put at the end of input
+            None => return,
+        }
+        self.next_location.offset += 1;
+    }
+
     fn get_token(&mut self) -> Option<Token> {
         let ch: char;
 
@@ -100,11 +240,15 @@ where
         if self.char_buffer.map_or(true, |c| c.is_ascii_whitespace()) {
             match self.try_get_char(true) {
                 Some(c) => ch = c,
-                None => return Token::EOF.into(),
+                None => {
+                    self.token_span.start = self.char_location;
+                    return Token::EOF.into();
+                }
             }
         } else {
             ch = self.char_buffer.unwrap();
         }
+        self.token_span.start = self.char_location;
 
         // Def, Extern, or Identifier
         if ch.is_ascii_alphabetic() {
@@ -118,6 +262,11 @@ where
         }
 
         self.try_get_char(false);
+        // Comparisons followed by '=' are operators of their own
+        if matches!(ch, '<' | '>' | '=' | '!') && self.char_buffer == Some('=') {
+            self.try_get_char(false);
+            return Token::Operator(format!("{}=", ch)).into();
+        }
         Token::Misc(ch).into()
     }
 
@@ -125,13 +274,27 @@ where
         self.char_buffer = None;
 
         loop {
-            // TODO: Improve error handling here
-            if read_exact!(self.reader, self.byte_buffer).is_err() {
-                return None;
-            }
+            // Anything read ahead is already in source
+            let read_ahead = match self.lookahead.pop_front() {
+                Some(byte) => {
+                    self.byte_buffer[0] = byte;
+                    true
+                }
+                // TODO: Improve error handling here
+                None if read_exact!(self.reader, self.byte_buffer).is_err() => {
+                    self.advance_location(None);
+                    return None;
+                }
+                None => false,
+            };
 
             if self.byte_buffer[0].is_ascii() {
-                self.char_buffer = char::from(self.byte_buffer[0]).into();
+                let c = char::from(self.byte_buffer[0]);
+                self.advance_location(Some(c));
+                if !read_ahead {
+                    self.source.push(c);
+                }
+                self.char_buffer = c.into();
             } else {
                 eprintln!(
                     "Read non-ASCII byte '{}' while Lexing, exiting...\n",
@@ -212,6 +375,16 @@ where
             "if" => Token::If,
             "then" => Token::Then,
             "else" => Token::Else,
+            "for" => Token::For,
+            "in" => Token::In,
+            "binary" => Token::Binary,
+            "unary" => Token::Unary,
+            "var" => Token::Var,
+            "while" => Token::While,
+            "do" => Token::Do,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "and" | "or" | "not" => Token::Operator(ident),
             _ => Token::Identifier(ident),
         }
         .into()
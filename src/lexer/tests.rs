@@ -247,3 +247,203 @@ fn test_lex_if_then_else() {
     assert_eq!(lexer.get_next_token(), &Token::Then.into());
     assert_eq!(lexer.get_next_token(), &Token::Else.into());
 }
+
+#[test]
+fn test_lex_for_in() {
+    let mut lexer = Lexer::new("for i in".as_bytes());
+    assert_eq!(lexer.get_next_token(), &Token::For.into());
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Identifier("i".into()).into()
+    );
+    assert_eq!(lexer.get_next_token(), &Token::In.into());
+}
+
+#[test]
+fn test_lex_binary_operator_definition() {
+    let mut lexer = Lexer::new("def binary| 5".as_bytes());
+    assert_eq!(lexer.get_next_token(), &Token::Def.into());
+    assert_eq!(lexer.get_next_token(), &Token::Binary.into());
+    assert_eq!(lexer.get_next_token(), &Token::Misc('|').into());
+    assert_eq!(lexer.get_next_token(), &Token::Number(5.0).into());
+}
+
+#[test]
+fn test_lex_unary_operator_definition() {
+    let mut lexer = Lexer::new("def unary!".as_bytes());
+    assert_eq!(lexer.get_next_token(), &Token::Def.into());
+    assert_eq!(lexer.get_next_token(), &Token::Unary.into());
+    assert_eq!(lexer.get_next_token(), &Token::Misc('!').into());
+}
+
+#[test]
+fn test_lex_comparison_operators() {
+    let mut lexer = Lexer::new("a<=b >= c==d!=e = f<g".as_bytes());
+    let mut operators = vec![];
+    loop {
+        match lexer.get_next_token() {
+            Some(Token::Operator(op)) => operators.push(op.clone()),
+            Some(Token::Misc(c)) => operators.push(c.to_string()),
+            Some(Token::EOF) | None => break,
+            _ => (),
+        }
+    }
+
+    assert_eq!(operators, vec!["<=", ">=", "==", "!=", "=", "<"]);
+}
+
+#[test]
+fn test_lex_logical_keywords() {
+    let mut lexer = Lexer::new("not a and b or android".as_bytes());
+
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Operator("not".into()).into()
+    );
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Identifier("a".into()).into()
+    );
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Operator("and".into()).into()
+    );
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Identifier("b".into()).into()
+    );
+    assert_eq!(lexer.get_next_token(), &Token::Operator("or".into()).into());
+    // Only whole words are keywords
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Identifier("android".into()).into()
+    );
+}
+
+#[test]
+fn test_lex_operator_span_covers_both_characters() {
+    let mut lexer = Lexer::new("a <= b".as_bytes());
+    lexer.get_next_token();
+
+    assert_eq!(lexer.get_next_token(), &Token::Operator("<=".into()).into());
+    assert_eq!(lexer.current_span().start.column, 3);
+    assert_eq!(lexer.current_span().end.column, 5);
+}
+
+#[test]
+fn test_lex_while_do_break_continue() {
+    let mut lexer = Lexer::new("while x do break continue".as_bytes());
+    assert_eq!(lexer.get_next_token(), &Token::While.into());
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Identifier("x".into()).into()
+    );
+    assert_eq!(lexer.get_next_token(), &Token::Do.into());
+    assert_eq!(lexer.get_next_token(), &Token::Break.into());
+    assert_eq!(lexer.get_next_token(), &Token::Continue.into());
+}
+
+#[test]
+fn test_lex_var_in() {
+    let mut lexer = Lexer::new("var x = 1 in".as_bytes());
+    assert_eq!(lexer.get_next_token(), &Token::Var.into());
+    assert_eq!(
+        lexer.get_next_token(),
+        &Token::Identifier("x".into()).into()
+    );
+    assert_eq!(lexer.get_next_token(), &Token::Misc('=').into());
+    assert_eq!(lexer.get_next_token(), &Token::Number(1.0).into());
+    assert_eq!(lexer.get_next_token(), &Token::In.into());
+}
+
+#[test]
+fn test_current_span_tracks_offsets_lines_and_columns() {
+    let mut lexer = Lexer::new("def foo(x)\n  x + 1".as_bytes());
+
+    let location = |offset, line, column| SourceLocation {
+        offset,
+        line,
+        column,
+    };
+    let expected_spans = [
+        (Token::Def, location(0, 1, 1), location(3, 1, 4)),
+        (
+            Token::Identifier("foo".into()),
+            location(4, 1, 5),
+            location(7, 1, 8),
+        ),
+        (Token::Misc('('), location(7, 1, 8), location(8, 1, 9)),
+        (
+            Token::Identifier("x".into()),
+            location(8, 1, 9),
+            location(9, 1, 10),
+        ),
+        (Token::Misc(')'), location(9, 1, 10), location(10, 1, 11)),
+        (
+            Token::Identifier("x".into()),
+            location(13, 2, 3),
+            location(14, 2, 4),
+        ),
+        (Token::Misc('+'), location(15, 2, 5), location(16, 2, 6)),
+        // The last token ends at the end of input
+        (Token::Number(1.0), location(17, 2, 7), location(18, 2, 8)),
+        (Token::EOF, location(18, 2, 8), location(18, 2, 8)),
+    ];
+    for (token, start, end) in expected_spans {
+        assert_eq!(lexer.get_next_token(), &token.into());
+        assert_eq!(lexer.current_span(), Span { start, end });
+    }
+}
+
+#[test]
+fn test_source_holds_everything_read_so_far() {
+    let mut lexer = Lexer::new("def foo(x)\n  x + 1".as_bytes());
+
+    lexer.get_next_token();
+    lexer.get_next_token();
+    // Lexing foo reads the '(' following it
+    assert_eq!(lexer.source(), "def foo(");
+
+    while lexer.get_next_token() != &Some(Token::EOF) {}
+    assert_eq!(lexer.source(), "def foo(x)\n  x + 1");
+}
+
+#[test]
+fn test_read_rest_of_line_is_still_lexed() {
+    let mut lexer = Lexer::new("def foo(x)\n  x + 1".as_bytes());
+
+    lexer.get_next_token();
+    lexer.read_rest_of_line();
+    assert_eq!(lexer.source(), "def foo(x)\n");
+    // Reading ahead again doesn't go past the line
+    lexer.read_rest_of_line();
+    assert_eq!(lexer.source(), "def foo(x)\n");
+
+    let mut tokens = vec![];
+    while lexer.get_next_token() != &Some(Token::EOF) {
+        tokens.push(lexer.current_token().clone().unwrap());
+    }
+    assert_eq!(tokens.len(), 7);
+    assert_eq!(tokens[0], Identifier("foo".into()));
+    assert_eq!(lexer.current_span().start.line, 2);
+    assert_eq!(lexer.source(), "def foo(x)\n  x + 1");
+}
+
+#[test]
+fn test_token_display() {
+    assert_eq!(
+        Token::Identifier("foo".into()).to_string(),
+        "identifier 'foo'"
+    );
+    assert_eq!(Token::Number(4.5).to_string(), "number 4.5");
+    assert_eq!(Token::Misc(';').to_string(), "';'");
+    assert_eq!(Token::EOF.to_string(), "end of input");
+}
+
+#[test]
+fn test_shebang_line_is_skipped() {
+    let mut lexer = Lexer::new("#!/usr/bin/env kaleidoscope\ndef foo".as_bytes());
+
+    assert_eq!(lexer.get_next_token(), &Token::Def.into());
+    assert_eq!(lexer.current_span().start.line, 2);
+}
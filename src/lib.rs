@@ -0,0 +1,33 @@
+#![feature(generic_associated_types)]
+
+//! A compiler for the Kaleidoscope language from LLVM's tutorial. `Session` is the simplest way
+//! to run code, the lexer, parser, codegen and driver are all usable on their own too. Codegen,
+//! the JIT and building executables need the default `llvm` feature
+
+pub mod ast;
+#[cfg(feature = "llvm")]
+pub mod build;
+#[cfg(feature = "llvm")]
+pub mod codegen;
+pub mod context;
+pub mod diagnostic;
+pub mod driver;
+pub mod environment;
+pub mod interpreter;
+pub mod lexer;
+#[cfg(feature = "llvm")]
+pub mod library;
+pub mod option_ext;
+pub mod parser;
+pub mod repl;
+pub mod session;
+mod test_utilities;
+
+pub use ast::Expr;
+#[cfg(feature = "llvm")]
+pub use codegen::CodeGen;
+pub use context::Context;
+pub use driver::{Drive, Driver, DriverOptions};
+pub use lexer::Lexer;
+pub use parser::Parser;
+pub use session::{Session, SessionError};
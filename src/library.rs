@@ -1,59 +1,56 @@
 use std::{
-    ffi::CStr,
-    io::{stderr, Write},
+    ffi::CString,
+    io::{Error, ErrorKind},
+    path::Path,
+    ptr,
 };
 
-macro_rules! cstr {
-    ($str:expr) => {
-        unsafe { CStr::from_bytes_with_nul_unchecked(concat!($str, "\0").as_bytes()) }
-    };
-}
+use llvm_sys::support::{LLVMAddSymbol, LLVMLoadLibraryPermanently, LLVMSearchForAddressOfSymbol};
 
-static PUTCHARD_NAME: &'static CStr = cstr!("putchard");
-#[no_mangle]
-pub extern "C" fn putchard(x: f64) -> f64 {
-    let mut stderr = stderr();
-    match write!(stderr, "{}", x as u8 as char).ok() {
-        Some(()) => (),
-        None => return 1.0,
-    }
-    match stderr.flush().ok() {
-        Some(()) => (),
-        None => return 1.0,
-    }
+use kaleidoscope_runtime::RUNTIME_FNS;
 
-    0.0
+/// Points the JIT at our own runtime functions, these win over anything else of the same name
+pub fn register_runtime_functions() {
+    for func in &RUNTIME_FNS {
+        unsafe { LLVMAddSymbol(func.name.as_ptr(), func.func_pointer.as_ptr()) };
+    }
 }
 
-static PRINTD_NAME: &'static CStr = cstr!("printd");
-#[no_mangle]
-pub extern "C" fn printd(x: f64) -> f64 {
-    let mut stderr = stderr();
-    match writeln!(stderr, "{}", x).ok() {
-        Some(()) => (),
-        None => return 1.0,
-    }
-    match stderr.flush().ok() {
-        Some(()) => (),
-        None => return 1.0,
+/// Makes every symbol in the running process visible to the JIT, which is where externs like
+/// `sin` and `sqrt` resolve to
+pub fn load_host_symbols() -> Result<(), Error> {
+    // A null file name means the process itself
+    if unsafe { LLVMLoadLibraryPermanently(ptr::null()) } != 0 {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "Unable to load symbols from the host process",
+        ));
     }
-
-    0.0
+    Ok(())
 }
 
-pub struct PrintFunc {
-    pub name: &'static CStr,
-    pub func_pointer: extern "C" fn(f64) -> f64,
+/// Loads a shared library so externs can resolve to the functions it exports
+pub fn load_library(path: &Path) -> Result<(), Error> {
+    let file_name = CString::new(path.to_string_lossy().as_bytes()).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{}: library paths can't contain nul bytes", path.display()),
+        )
+    })?;
+
+    if unsafe { LLVMLoadLibraryPermanently(file_name.as_ptr()) } != 0 {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("{}: unable to load library", path.display()),
+        ));
+    }
+    Ok(())
 }
 
-#[used]
-pub static PRINT_FNS: [PrintFunc; 2] = [
-    PrintFunc {
-        name: PUTCHARD_NAME,
-        func_pointer: putchard,
-    },
-    PrintFunc {
-        name: PRINTD_NAME,
-        func_pointer: printd,
-    },
-];
+/// Whether the JIT will be able to find `name` outside of the code it compiled
+pub fn is_loaded(name: &str) -> bool {
+    match CString::new(name) {
+        Ok(name) => !unsafe { LLVMSearchForAddressOfSymbol(name.as_ptr()) }.is_null(),
+        Err(_) => false,
+    }
+}
@@ -1,28 +1,50 @@
-#![feature(generic_associated_types)]
-
-use driver::{Drive, Driver, DriverOptions};
-use inkwell::context::Context;
+#[cfg(feature = "llvm")]
+use kaleidoscope::{build, driver::Command};
+use kaleidoscope::{
+    driver::{Drive, Driver, DriverOptions},
+    repl::Repl,
+    Context,
+};
 use std::io::{stdin, stdout};
 
-mod ast;
-mod codegen;
-mod driver;
-mod environment;
-mod lexer;
-mod library;
-mod option_ext;
-mod parser;
-mod test_utilities;
-
 use clap::Parser;
 
 fn main() -> Result<(), std::io::Error> {
-    let options = DriverOptions::parse();
+    #[cfg_attr(not(feature = "llvm"), allow(unused_mut))]
+    let mut options = DriverOptions::parse();
     let context = Context::create();
-    let mut driver =
-        Driver::new(Box::new(stdin()), Box::new(stdout()), &context).with_options(options);
+    #[cfg(feature = "llvm")]
+    if let Some(Command::Build(build_options)) = options.command.take() {
+        return build::build(&context, build_options);
+    }
 
-    driver.run()?;
+    let files = options.files.clone();
+    // Piped input doesn't get a prompt, and typed input goes to the REPL, which has its own
+    let mut driver = Driver::new(Box::new(stdin()), Box::new(stdout()), &context)
+        .with_options(options)
+        .with_prompt(false);
+    driver.load_libraries()?;
+
+    let is_interactive = files.is_empty() && atty::is(atty::Stream::Stdin);
+    if is_interactive {
+        Repl::new(&mut driver)?.run()?;
+    } else if files.is_empty() {
+        driver.run()?;
+    } else {
+        for file in &files {
+            driver.run_file(file)?;
+        }
+    }
     driver.dump_ir()?;
+
+    // Mistakes made interactively have already been dealt with
+    if !is_interactive && driver.error_count() > 0 {
+        std::process::exit(1);
+    }
+    let errors_before = driver.error_count();
+    driver.emit()?;
+    if driver.error_count() > errors_before {
+        std::process::exit(1);
+    }
     Ok(())
 }
@@ -1,6 +1,7 @@
 use crate::{
-    ast::{Expr, ExprKind, IfVal},
-    environment::Environment,
+    ast::{Expr, ExprKind, ForVal, IfVal, VarVal, WhileVal},
+    diagnostic::{code, Diagnostic},
+    environment::{Associativity, Environment},
     lexer::{Lex, Token},
     option_ext::OptionExt,
 };
@@ -8,46 +9,125 @@ use crate::{
 pub trait Parse {
     fn new() -> Self;
     fn parse_number_expr<L: Lex>(&mut self, lexer: &mut L) -> Expr;
-    fn parse_paren_expr<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
+    fn parse_paren_expr<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
     fn parse_identifier_prefixed_expr<L: Lex>(
         &mut self,
         identifier: String,
         lexer: &mut L,
-    ) -> Option<Expr>;
-    fn parse_if_then_else<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
-    fn parse_primary_expr<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
-    fn parse_expression<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
+    ) -> Result<Expr, Diagnostic>;
+    fn parse_if_then_else<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_for_in<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_var_in<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_while_do<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_loop_control<L: Lex>(&mut self, lexer: &mut L) -> Expr;
+    fn parse_block<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_primary_expr<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_unary<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_expression<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
     fn parse_binary_op_rhs<L: Lex>(
         &mut self,
         lowest_edible_op_precedence: i32,
         lhs: Expr,
         lexer: &mut L,
-    ) -> Option<Expr>;
-    fn parse_function_prototype<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
-    fn parse_function_definition<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
-    fn parse_extern<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
-    fn parse_top_level_expression<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr>;
+    ) -> Result<Expr, Diagnostic>;
+    fn parse_function_prototype<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_function_definition<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_extern<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
+    fn parse_top_level_expression<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic>;
 }
 
 pub struct Parser {
     pub environment: Environment,
+    warnings: Vec<Diagnostic>,
+    // Blocks opened but not yet closed, which is only ever non-zero after an error
+    open_blocks: usize,
+}
+
+const DEFAULT_BINARY_PRECEDENCE: i32 = 30;
+// Operators codegen handles itself, user definitions of them are never called
+const BUILTIN_BINARY_OPERATORS: [(&str, i32); 15] = [
+    ("=", 2),
+    ("or", 4),
+    ("and", 6),
+    ("==", 9),
+    ("!=", 9),
+    ("<", 10),
+    (">", 10),
+    ("<=", 10),
+    (">=", 10),
+    ("+", 20),
+    ("-", 30),
+    ("*", 40),
+    ("/", 40),
+    ("%", 40),
+    ("^", 50),
+];
+// Builtin operators that group from the right, any others group from the left
+const RIGHT_ASSOCIATIVE_OPERATORS: [&str; 2] = ["=", "^"];
+
+// How an operator token is spelled, punctuation that can never be an operator isn't one
+fn operator(token: &Option<Token>) -> Option<String> {
+    match token {
+        Some(Token::Misc(c)) if !matches!(c, '(' | ')' | ',' | ';' | '{' | '}') => {
+            Some(c.to_string())
+        }
+        Some(Token::Operator(op)) => Some(op.clone()),
+        _ => None,
+    }
 }
 
 impl Parser {
-    fn log_error(&self, str: String) -> Option<Expr> {
-        eprintln!("log_error: {}", str);
-        None
+    // Reports that the current token isn't what the grammar expected
+    fn expected<L: Lex>(&self, lexer: &L, expected: &str) -> Diagnostic {
+        let got = match lexer.current_token() {
+            Some(tok) => tok.to_string(),
+            None => "nothing".into(),
+        };
+        Diagnostic::error(
+            code::UNEXPECTED_TOKEN,
+            format!("Expected {}, got {}", expected, got),
+            lexer.current_span(),
+        )
+    }
+
+    /// Non-fatal diagnostics reported since the last call
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// How many blocks the last error left unclosed, so recovery can skip to the end of them
+    pub fn take_open_blocks(&mut self) -> usize {
+        std::mem::take(&mut self.open_blocks)
+    }
+
+    // Each top level item starts outside of any block, whatever an earlier error that was never
+    // recovered from left behind
+    fn start_item(&mut self) {
+        self.open_blocks = 0;
+    }
+
+    // Eats the 'binary'/'unary' keyword, leaving the operator character as the current token
+    fn parse_operator_name<L: Lex>(&self, lexer: &mut L) -> Result<String, Diagnostic> {
+        lexer.get_next_token();
+        operator(lexer.current_token()).ok_or_else(|| self.expected(lexer, "an operator"))
     }
 }
 
 impl Parse for Parser {
     fn new() -> Self {
         let mut environment = Environment::new();
-        [('<', 10), ('+', 20), ('-', 30), ('*', 40)]
+        BUILTIN_BINARY_OPERATORS
             .iter()
             .for_each(|p| environment.add_operator_precedence(*p));
-
-        Parser { environment }
+        RIGHT_ASSOCIATIVE_OPERATORS.iter().for_each(|operator| {
+            environment.set_operator_associativity(operator, Associativity::Right)
+        });
+
+        Parser {
+            environment,
+            warnings: vec![],
+            open_blocks: 0,
+        }
     }
 
     // Primary expression parsing
@@ -58,33 +138,33 @@ impl Parse for Parser {
         };
         let result = Expr {
             kind: ExprKind::Number(value),
+            span: lexer.current_span(),
         };
         lexer.get_next_token();
         result
     }
 
-    fn parse_paren_expr<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
+    fn parse_paren_expr<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
         // Eat '('
         lexer.get_next_token();
         let result = self.parse_expression(lexer)?;
 
         match lexer.current_token() {
             Some(Token::Misc(')')) => (),
-            Some(Token::Misc(c)) => return self.log_error(format!("Expected ')' but got {}!", c)),
-            Some(tok) => return self.log_error(format!("Expected ')' but got {:#?}", tok)),
-            None => return self.log_error("Expected ')' but got None!".into()),
+            _ => return Err(self.expected(lexer, "')'")),
         }
         // Eat ')'
         lexer.get_next_token();
 
-        result.into()
+        Ok(result)
     }
 
     fn parse_identifier_prefixed_expr<L: Lex>(
         &mut self,
         identifier: String,
         lexer: &mut L,
-    ) -> Option<Expr> {
+    ) -> Result<Expr, Diagnostic> {
+        let span = lexer.current_span();
         // Eat the identifier
         lexer.get_next_token();
 
@@ -92,10 +172,10 @@ impl Parse for Parser {
             Some(Token::Misc('(')) => lexer.get_next_token(),
             _ => {
                 // This is a Variable expr, not a Call expr, so we're done
-                return Expr {
+                return Ok(Expr {
                     kind: ExprKind::Variable { name: identifier },
-                }
-                .into();
+                    span,
+                });
             }
         };
 
@@ -111,12 +191,13 @@ impl Parse for Parser {
             match lexer.current_token() {
                 Some(Token::Misc(')')) => break,
                 Some(Token::Misc(',')) => (),
-                _ => return self.log_error("Expected ')' or ','".into()),
+                _ => return Err(self.expected(lexer, "')' or ','")),
             };
             lexer.get_next_token();
         }
 
-        // Eat the closing parenthese
+        // The call ends with the closing parenthese
+        let span = span.to(lexer.current_span());
         lexer.get_next_token();
 
         let kind = ExprKind::Call {
@@ -124,17 +205,18 @@ impl Parse for Parser {
             args: call_args,
         };
 
-        Expr { kind }.into()
+        Ok(Expr { kind, span })
     }
 
-    fn parse_if_then_else<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
+    fn parse_if_then_else<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        let span = lexer.current_span();
         lexer.get_next_token().discard();
         let maybe_test_expr = self.parse_expression(lexer)?;
 
         // We've loaded if <expr> at this point
         match lexer.current_token() {
             Some(Token::Then) => (),
-            _ => return None,
+            _ => return Err(self.expected(lexer, "'then' after if")),
         }
 
         lexer.get_next_token().discard();
@@ -143,39 +225,238 @@ impl Parse for Parser {
         // Now we've loaded if <expr> then <expr>
         match lexer.current_token() {
             Some(Token::Else) => (),
-            _ => return None,
+            _ => return Err(self.expected(lexer, "'else' after then")),
         }
 
         // Parse the last <expr>
         lexer.get_next_token().discard();
         let maybe_else_expr = self.parse_expression(lexer)?;
+        let span = span.to(maybe_else_expr.span);
 
-        Expr {
+        Ok(Expr {
             kind: ExprKind::If(IfVal {
                 if_boolish_test: maybe_test_expr.into(),
                 then: maybe_then_expr.into(),
                 elves: maybe_else_expr.into(),
             }),
+            span,
+        })
+    }
+
+    fn parse_for_in<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        let span = lexer.current_span();
+        // Eat 'for'
+        lexer.get_next_token().discard();
+
+        let var_name = match lexer.current_token() {
+            Some(Token::Identifier(ident)) => ident.clone(),
+            _ => return Err(self.expected(lexer, "identifier after for")),
+        };
+        lexer.get_next_token().discard();
+
+        match lexer.current_token() {
+            Some(Token::Misc('=')) => (),
+            _ => return Err(self.expected(lexer, "'=' after for")),
         }
-        .into()
+        lexer.get_next_token().discard();
+        let start = self.parse_expression(lexer)?;
+
+        match lexer.current_token() {
+            Some(Token::Misc(',')) => (),
+            _ => return Err(self.expected(lexer, "',' after for start")),
+        }
+        lexer.get_next_token().discard();
+        let end = self.parse_expression(lexer)?;
+
+        // The step value is optional
+        let step = match lexer.current_token() {
+            Some(Token::Misc(',')) => {
+                lexer.get_next_token().discard();
+                Some(Box::new(self.parse_expression(lexer)?))
+            }
+            _ => None,
+        };
+
+        match lexer.current_token() {
+            Some(Token::In) => (),
+            _ => return Err(self.expected(lexer, "'in' after for")),
+        }
+        lexer.get_next_token().discard();
+        let body = self.parse_expression(lexer)?;
+        let span = span.to(body.span);
+
+        Ok(Expr {
+            kind: ExprKind::For(ForVal {
+                var_name,
+                start: start.into(),
+                end: end.into(),
+                step,
+                body: body.into(),
+            }),
+            span,
+        })
     }
 
-    fn parse_primary_expr<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
+    fn parse_var_in<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        let span = lexer.current_span();
+        // Eat 'var'
+        lexer.get_next_token().discard();
+
+        let mut bindings = vec![];
+        loop {
+            let var_name = match lexer.current_token() {
+                Some(Token::Identifier(ident)) => ident.clone(),
+                _ => return Err(self.expected(lexer, "identifier after var")),
+            };
+            lexer.get_next_token().discard();
+
+            // The initializer is optional
+            let initializer = match lexer.current_token() {
+                Some(Token::Misc('=')) => {
+                    lexer.get_next_token().discard();
+                    Some(Box::new(self.parse_expression(lexer)?))
+                }
+                _ => None,
+            };
+            bindings.push((var_name, initializer));
+
+            // Keep going while there are more comma separated bindings
+            match lexer.current_token() {
+                Some(Token::Misc(',')) => lexer.get_next_token().discard(),
+                _ => break,
+            }
+        }
+
+        match lexer.current_token() {
+            Some(Token::In) => (),
+            _ => return Err(self.expected(lexer, "'in' after var")),
+        }
+        lexer.get_next_token().discard();
+        let body = self.parse_expression(lexer)?;
+        let span = span.to(body.span);
+
+        Ok(Expr {
+            kind: ExprKind::Var(VarVal {
+                bindings,
+                body: body.into(),
+            }),
+            span,
+        })
+    }
+
+    fn parse_while_do<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        let span = lexer.current_span();
+        // Eat 'while'
+        lexer.get_next_token().discard();
+        let condition = self.parse_expression(lexer)?;
+
+        match lexer.current_token() {
+            Some(Token::Do) => (),
+            _ => return Err(self.expected(lexer, "'do' after while")),
+        }
+        lexer.get_next_token().discard();
+        let body = self.parse_expression(lexer)?;
+        let span = span.to(body.span);
+
+        Ok(Expr {
+            kind: ExprKind::While(WhileVal {
+                condition: condition.into(),
+                body: body.into(),
+            }),
+            span,
+        })
+    }
+
+    // Whether break and continue are actually inside a loop is up to whatever runs them
+    fn parse_loop_control<L: Lex>(&mut self, lexer: &mut L) -> Expr {
+        let kind = match lexer.current_token() {
+            Some(Token::Break) => ExprKind::Break,
+            Some(Token::Continue) => ExprKind::Continue,
+            _ => unreachable!("lexer should have loaded break or continue prior to calling this"),
+        };
+        let span = lexer.current_span();
+        lexer.get_next_token().discard();
+
+        Expr { kind, span }
+    }
+
+    fn parse_block<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        let span = lexer.current_span();
+        // Eat '{'
+        lexer.get_next_token().discard();
+        self.open_blocks += 1;
+
+        let mut expressions = vec![];
+        loop {
+            expressions.push(self.parse_expression(lexer)?);
+
+            // Expressions are separated by ';', which is optional after the last one
+            match lexer.current_token() {
+                Some(Token::Misc(';')) => {
+                    lexer.get_next_token().discard();
+                    if let Some(Token::Misc('}')) = lexer.current_token() {
+                        break;
+                    }
+                }
+                Some(Token::Misc('}')) => break,
+                _ => return Err(self.expected(lexer, "';' or '}' in block")),
+            }
+        }
+
+        let span = span.to(lexer.current_span());
+        // Eat '}'
+        lexer.get_next_token().discard();
+        self.open_blocks -= 1;
+
+        Ok(Expr {
+            kind: ExprKind::Block(expressions),
+            span,
+        })
+    }
+
+    fn parse_primary_expr<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
         match lexer.current_token() {
             Some(Token::Identifier(ident)) => {
                 self.parse_identifier_prefixed_expr(ident.clone(), lexer)
             }
-            Some(Token::Number(_)) => self.parse_number_expr(lexer).into(),
+            Some(Token::Number(_)) => Ok(self.parse_number_expr(lexer)),
             Some(Token::Misc('(')) => self.parse_paren_expr(lexer),
             Some(Token::If) => self.parse_if_then_else(lexer),
-            _ => self.log_error("unknown token when expecting an expression".into()),
+            Some(Token::For) => self.parse_for_in(lexer),
+            Some(Token::Var) => self.parse_var_in(lexer),
+            Some(Token::While) => self.parse_while_do(lexer),
+            Some(Token::Break | Token::Continue) => Ok(self.parse_loop_control(lexer)),
+            Some(Token::Misc('{')) => self.parse_block(lexer),
+            _ => Err(self.expected(lexer, "an expression")),
         }
     }
 
     // Operator parsing and precedence stuff
-    fn parse_expression<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
-        let primary = self.parse_primary_expr(lexer)?;
-        self.parse_binary_op_rhs(0, primary, lexer)
+    fn parse_unary<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        // If the current token isn't an operator, this must be a primary expression
+        let op = match operator(lexer.current_token()) {
+            Some(op) => op,
+            None => return self.parse_primary_expr(lexer),
+        };
+
+        // Eat the operator, then parse its operand, which may itself be a unary expression
+        let span = lexer.current_span();
+        lexer.get_next_token();
+        let operand = self.parse_unary(lexer)?;
+        let span = span.to(operand.span);
+
+        Ok(Expr {
+            kind: ExprKind::Unary {
+                operator: op,
+                operand: Box::new(operand),
+            },
+            span,
+        })
+    }
+
+    fn parse_expression<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        let lhs = self.parse_unary(lexer)?;
+        self.parse_binary_op_rhs(0, lhs, lexer)
     }
 
     fn parse_binary_op_rhs<L: Lex>(
@@ -183,71 +464,92 @@ impl Parse for Parser {
         lowest_edible_op_precedence: i32,
         mut lhs: Expr,
         lexer: &mut L,
-    ) -> Option<Expr> {
+    ) -> Result<Expr, Diagnostic> {
         loop {
             // Try looking up precedence and default to -1 (which is worst than
             // any precedence) if this fails
-            let (precedence, op): (i32, Option<char>) = match lexer.current_token() {
-                Some(Token::Misc(c)) => (
-                    self.environment.get_operator_precedence(*c).unwrap_or(-1),
-                    Some(*c),
-                ),
-                _ => (-1, None),
-            };
+            let op = operator(lexer.current_token());
+            let precedence = op
+                .as_ref()
+                .and_then(|op| self.environment.get_operator_precedence(op))
+                .unwrap_or(-1);
 
             // Checking if precedence is high enough priority to eat
             if lowest_edible_op_precedence > precedence {
-                return lhs.into();
+                return Ok(lhs);
             }
 
             let op = op.unwrap();
             lexer.get_next_token();
-            let mut rhs = self.parse_primary_expr(lexer)?;
-
-            // Checking if there is a higher precedence operator to the RHS
-            let next_precedence = match lexer.current_token() {
-                Some(Token::Misc(c)) => self.environment.get_operator_precedence(*c).unwrap_or(-1),
-                _ => -1,
-            };
-            if next_precedence > precedence {
+            let mut rhs = self.parse_unary(lexer)?;
+
+            // Checking if there is a higher precedence operator to the RHS, or the same one again
+            // when it groups from the right
+            let next_precedence = operator(lexer.current_token())
+                .and_then(|op| self.environment.get_operator_precedence(&op))
+                .unwrap_or(-1);
+            let is_right_associative =
+                self.environment.get_operator_associativity(&op) == Associativity::Right;
+            if next_precedence > precedence
+                || (is_right_associative && next_precedence == precedence)
+            {
                 // If so, recurse to the rhs
-                rhs = self.parse_binary_op_rhs(precedence + 1, rhs, lexer)?;
+                let lowest_rhs_precedence = if is_right_associative {
+                    precedence
+                } else {
+                    precedence + 1
+                };
+                rhs = self.parse_binary_op_rhs(lowest_rhs_precedence, rhs, lexer)?;
             }
+            let span = lhs.span.to(rhs.span);
             lhs = Expr {
                 kind: ExprKind::Binary {
                     operator: op,
                     lhs: Box::new(lhs),
                     rhs: Box::new(rhs),
                 },
+                span,
             };
         }
     }
-    fn parse_function_prototype<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
-        let func_name: Option<String> = match lexer.current_token() {
-            Some(Token::Identifier(i)) => Some(i.clone()),
-            _ => None,
+    fn parse_function_prototype<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        let span = lexer.current_span();
+        // Operator definitions also record the operator and how many operands it takes
+        let (func_name, operator): (String, Option<(String, usize)>) = match lexer.current_token() {
+            Some(Token::Identifier(i)) => (i.clone(), None),
+            Some(Token::Binary) => {
+                let op = self.parse_operator_name(lexer)?;
+                (format!("binary{}", op), Some((op, 2)))
+            }
+            Some(Token::Unary) => {
+                let op = self.parse_operator_name(lexer)?;
+                (format!("unary{}", op), Some((op, 1)))
+            }
+            // May want to consume the token here?
+            _ => return Err(self.expected(lexer, "function name in prototype")),
         };
+        lexer.get_next_token();
 
-        // May want to consume the token here?
-        if func_name.is_none() {
-            return self.log_error(format!(
-                "Expected function name in protype,\n  got {:#?}",
-                lexer.current_token()
-            ));
+        // Binary operators may be followed by their precedence
+        let mut binary_precedence = DEFAULT_BINARY_PRECEDENCE;
+        if let (Some((_, 2)), Some(Token::Number(precedence))) = (&operator, lexer.current_token())
+        {
+            if !(1.0..=100.0).contains(precedence) {
+                return Err(Diagnostic::error(
+                    code::INVALID_OPERATOR_DEFINITION,
+                    format!("Invalid precedence {}", precedence),
+                    lexer.current_span(),
+                )
+                .with_note("precedences must be between 1 and 100"));
+            }
+            binary_precedence = *precedence as i32;
+            lexer.get_next_token();
         }
 
-        let func_name = func_name.unwrap();
-        lexer.get_next_token();
-
         // Opening (
         match lexer.current_token() {
             Some(Token::Misc('(')) => (),
-            _ => {
-                return self.log_error(format!(
-                    "Expected '(' in prototype,\n  got {:#?}",
-                    lexer.current_token()
-                ))
-            }
+            _ => return Err(self.expected(lexer, "'(' in prototype")),
         }
         lexer.get_next_token();
 
@@ -257,80 +559,120 @@ impl Parse for Parser {
             _ => None,
         } {
             arg_names.push(ident.to_string());
-            // This should be a ','
+            // This should be a ',', ')' or the next argument
             lexer.get_next_token();
 
             match lexer.current_token() {
                 // Reached the end of the arguments, keep this in the lexer's
                 // buffer for the match following this loop
                 Some(Token::Misc(')')) => (),
-                // TODO: The tutorial doesn't require (or allow?) commas between parameters, should change this here.
                 // Another argument may follow (we allow trailing commas)
                 Some(Token::Misc(',')) => lexer.get_next_token().discard(),
-                _ => {
-                    return self.log_error(format!(
-                        "Expected ',' or ')' in prototype,\n  got {:#?}",
-                        lexer.current_token()
-                    ))
-                }
+                // Like the tutorial, arguments may also just be separated by whitespace
+                Some(Token::Identifier(_)) => (),
+                _ => return Err(self.expected(lexer, "',' or ')' in prototype")),
             }
         }
 
         match lexer.current_token() {
             Some(Token::Misc(')')) => (),
-            _ => {
-                return self.log_error(format!(
-                    "Expected ')' in prototype,\n  got {:#?}",
-                    lexer.current_token()
-                ))
-            }
+            _ => return Err(self.expected(lexer, "')' in prototype")),
         }
+        let span = span.to(lexer.current_span());
         lexer.get_next_token();
 
-        Expr {
+        if let Some((op, arity)) = operator {
+            if arg_names.len() != arity {
+                return Err(Diagnostic::error(
+                    code::INVALID_OPERATOR_DEFINITION,
+                    format!(
+                        "Operator {} expects {} operand(s), but got {}",
+                        op,
+                        arity,
+                        arg_names.len()
+                    ),
+                    span,
+                ));
+            }
+            // Register the operator right away so the rest of the input can use it
+            if arity == 2 {
+                if BUILTIN_BINARY_OPERATORS
+                    .iter()
+                    .any(|(builtin, _)| *builtin == op)
+                {
+                    self.warnings.push(
+                        Diagnostic::warning(
+                            code::SHADOWED_BUILTIN_OPERATOR,
+                            format!(
+                                "Operator {} is built in, this definition is never called",
+                                op
+                            ),
+                            span,
+                        )
+                        .with_note(format!(
+                            "its precedence still becomes {}",
+                            binary_precedence
+                        )),
+                    );
+                }
+                self.environment
+                    .add_operator_precedence((&op, binary_precedence));
+            }
+        }
+
+        Ok(Expr {
             kind: ExprKind::Prototype {
                 args: arg_names,
                 name: func_name,
             },
-        }
-        .into()
+            span,
+        })
     }
 
-    fn parse_function_definition<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
+    fn parse_function_definition<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        self.start_item();
+        let span = lexer.current_span();
         // Eat 'def'
         lexer.get_next_token();
         let prototype = self.parse_function_prototype(lexer)?;
         let expression = self.parse_expression(lexer)?;
+        let span = span.to(expression.span);
 
-        Expr {
+        Ok(Expr {
             kind: ExprKind::Function {
                 prototype: Box::new(prototype),
                 body: Box::new(expression),
             },
-        }
-        .into()
+            span,
+        })
     }
 
-    fn parse_extern<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
+    fn parse_extern<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        self.start_item();
         lexer.get_next_token();
         self.parse_function_prototype(lexer)
     }
 
     // Handle top level expressions by defining zero argument functions containing the expr
-    fn parse_top_level_expression<L: Lex>(&mut self, lexer: &mut L) -> Option<Expr> {
+    fn parse_top_level_expression<L: Lex>(&mut self, lexer: &mut L) -> Result<Expr, Diagnostic> {
+        self.start_item();
         let expression = self.parse_expression(lexer)?;
+        let span = expression.span;
         let prototype = ExprKind::Prototype {
             name: "__anon".to_string(),
             args: vec![],
         };
 
-        Expr {
+        Ok(Expr {
             kind: ExprKind::Function {
-                prototype: Box::new(Expr { kind: prototype }),
+                prototype: Box::new(Expr {
+                    kind: prototype,
+                    span,
+                }),
                 body: Box::new(expression),
             },
-        }
-        .into()
+            span,
+        })
     }
 }
 
@@ -2,7 +2,8 @@ use std::str::FromStr;
 
 use super::*;
 use crate::{
-    lexer::{Lex, Lexer},
+    diagnostic::Severity,
+    lexer::{Lex, Lexer, SourceLocation, Span},
     parser::ExprKind::*,
     test_utilities::test::approx_equal,
 };
@@ -23,10 +24,71 @@ macro_rules! setup_parser_lexer {
 fn test_new_sets_up_operator_precedences() {
     let parser = Parser::new();
 
-    assert_eq!(parser.environment.get_operator_precedence('<'), 10.into());
-    assert_eq!(parser.environment.get_operator_precedence('+'), 20.into());
-    assert_eq!(parser.environment.get_operator_precedence('-'), 30.into());
-    assert_eq!(parser.environment.get_operator_precedence('*'), 40.into());
+    assert_eq!(parser.environment.get_operator_precedence("="), 2.into());
+    assert_eq!(parser.environment.get_operator_precedence("<"), 10.into());
+    assert_eq!(parser.environment.get_operator_precedence("+"), 20.into());
+    assert_eq!(parser.environment.get_operator_precedence("-"), 30.into());
+    assert_eq!(parser.environment.get_operator_precedence("*"), 40.into());
+    assert_eq!(parser.environment.get_operator_precedence("/"), 40.into());
+    assert_eq!(parser.environment.get_operator_precedence("%"), 40.into());
+    assert_eq!(parser.environment.get_operator_precedence("^"), 50.into());
+    assert_eq!(parser.environment.get_operator_precedence("or"), 4.into());
+    assert_eq!(parser.environment.get_operator_precedence("and"), 6.into());
+}
+
+#[test]
+fn test_new_sets_up_operator_associativity() {
+    let parser = Parser::new();
+
+    assert_eq!(
+        parser.environment.get_operator_associativity("^"),
+        Associativity::Right
+    );
+    assert_eq!(
+        parser.environment.get_operator_associativity("/"),
+        Associativity::Left
+    );
+    // Operators that haven't been defined yet group from the left too
+    assert_eq!(
+        parser.environment.get_operator_associativity("|"),
+        Associativity::Left
+    );
+}
+
+// Writes an expression back out with every binary operation parenthesized
+fn grouping(expr: &Expr) -> String {
+    match &expr.kind {
+        Binary { operator, lhs, rhs } => {
+            format!("({} {} {})", grouping(lhs), operator, grouping(rhs))
+        }
+        Unary { operator, operand } => format!("({} {})", operator, grouping(operand)),
+        Number(n) => n.to_string(),
+        Variable { name } => name.clone(),
+        _ => panic!("Unexpected expression {:#?}", expr),
+    }
+}
+
+#[test]
+fn test_parse_operator_grouping() {
+    let cases = [
+        ("2 ^ 3 ^ 2", "(2 ^ (3 ^ 2))"),
+        ("8 / 4 / 2", "((8 / 4) / 2)"),
+        ("8 - 4 - 2", "((8 - 4) - 2)"),
+        ("7 % 4 * 2", "((7 % 4) * 2)"),
+        ("a or b and c or d", "((a or (b and c)) or d)"),
+        ("a < 1 and b == 2", "((a < 1) and (b == 2))"),
+        ("not a and b", "((not a) and b)"),
+        ("2 * 3 ^ 2 ^ a + 1", "((2 * (3 ^ (2 ^ a))) + 1)"),
+        ("a = b = 2 ^ 2", "(a = (b = (2 ^ 2)))"),
+    ];
+
+    for (source, expected) in cases {
+        let mut parser = Parser::new();
+        let mut lexer = Lexer::new(source.as_bytes());
+        lexer.get_next_token();
+        let result = parser.parse_expression(&mut lexer).unwrap();
+        assert_eq!(grouping(&result), expected, "{}", source);
+    }
 }
 
 #[test]
@@ -36,7 +98,9 @@ fn test_parse_number_expr_creates_number_expr() {
     let result = parser.parse_number_expr(&mut lexer);
 
     match result {
-        Expr { kind: Number(val) } => assert!(approx_equal(64.0, val, 5)),
+        Expr {
+            kind: Number(val), ..
+        } => assert!(approx_equal(64.0, val, 5)),
         _ => assert!(false, "Expected ExprKind::Number"),
     }
 }
@@ -68,7 +132,9 @@ fn test_parse_paren_expr() {
     let result = parser.parse_paren_expr(&mut lexer);
 
     match result {
-        Some(Expr { kind: Number(val) }) => assert!(approx_equal(val, 78.0, 5)),
+        Ok(Expr {
+            kind: Number(val), ..
+        }) => assert!(approx_equal(val, 78.0, 5)),
         _ => assert!(false, "Expected Expr::Kind(Number(78))"),
     }
 }
@@ -82,9 +148,10 @@ fn test_parse_identifier_prefixed_expr_parses_variable() {
         kind: Variable {
             name: "ident42".into(),
         },
+        span: Default::default(),
     };
     match result {
-        Some(expr) if expr == expected_value => (),
+        Ok(expr) if expr == expected_value => (),
         _ => assert!(false, "Expected {:#?}", expected_value),
     }
 }
@@ -96,13 +163,17 @@ fn test_parse_identifier_prefixed_expr_parses_call() {
     let result = parser.parse_identifier_prefixed_expr("ident42".into(), &mut lexer);
     let expected_value = Expr {
         kind: Call {
-            args: vec![Expr { kind: Number(30.0) }],
+            args: vec![Expr {
+                kind: Number(30.0),
+                span: Default::default(),
+            }],
             callee: "ident42".into(),
         },
+        span: Default::default(),
     };
     match result {
         // TODO: Not a great thing to be relying on equality of f64...
-        Some(expr) if expr == expected_value => (),
+        Ok(expr) if expr == expected_value => (),
         _ => assert!(false, "Expected {:#?}", expected_value),
     }
 }
@@ -115,15 +186,25 @@ fn test_parse_identifier_prefixed_expr_parsed_call_multiple_args() {
     let expected_value = Expr {
         kind: Call {
             args: vec![
-                Expr { kind: Number(30.0) },
-                Expr { kind: Number(60.0) },
-                Expr { kind: Number(90.0) },
+                Expr {
+                    kind: Number(30.0),
+                    span: Default::default(),
+                },
+                Expr {
+                    kind: Number(60.0),
+                    span: Default::default(),
+                },
+                Expr {
+                    kind: Number(90.0),
+                    span: Default::default(),
+                },
             ],
             callee: "ident42".into(),
         },
+        span: Default::default(),
     };
     match result {
-        Some(expr) if expr == expected_value => (),
+        Ok(expr) if expr == expected_value => (),
         _ => assert!(false, "Expected {:#?}", expected_value),
     }
 }
@@ -136,10 +217,10 @@ fn test_parse_primary_expr_parses_number() {
 
     assert_eq!(
         result,
-        Expr {
-            kind: Number(657.0)
-        }
-        .into()
+        Ok(Expr {
+            kind: Number(657.0),
+            span: Default::default()
+        })
     );
 }
 
@@ -150,12 +231,12 @@ fn test_parse_primary_expr_parses_ident_prefix_into_variable() {
     let result = parser.parse_primary_expr(&mut lexer);
     assert_eq!(
         result,
-        Expr {
+        Ok(Expr {
             kind: Variable {
                 name: "suwooooo".into()
-            }
-        }
-        .into()
+            },
+            span: Default::default()
+        })
     );
 }
 
@@ -166,13 +247,13 @@ fn test_parse_primary_expr_parses_ident_prefix_into_call() {
     let result = parser.parse_primary_expr(&mut lexer);
     assert_eq!(
         result,
-        Expr {
+        Ok(Expr {
             kind: Call {
                 args: vec![],
                 callee: "suwooooo".into()
-            }
-        }
-        .into()
+            },
+            span: Default::default()
+        })
     );
 }
 
@@ -181,20 +262,25 @@ fn test_parse_primary_expr_parses_paren_expr() {
     let (mut parser, mut lexer) = setup_parser_lexer!("(5 + yar())");
 
     let result = parser.parse_primary_expr(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Binary {
-            lhs: Expr { kind: Number(5.0) }.into(),
+            lhs: Expr {
+                kind: Number(5.0),
+                span: Default::default(),
+            }
+            .into(),
             rhs: Expr {
                 kind: Call {
                     callee: "yar".into(),
                     args: vec![],
                 },
+                span: Default::default(),
             }
             .into(),
-            operator: '+',
+            operator: "+".into(),
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -205,7 +291,7 @@ fn test_parse_primary_expr_logs_error() {
     let (mut parser, mut lexer) = setup_parser_lexer!("def");
 
     let result = parser.parse_primary_expr(&mut lexer);
-    assert_eq!(result, None);
+    assert!(result.is_err());
 }
 
 #[test]
@@ -214,35 +300,58 @@ fn test_parse_multiple_ops() {
 
     let result = parser.parse_expression(&mut lexer);
     // This is a mess to look at, but it represents (3 + (2 - (4 * 7))) < 3
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Binary {
-            operator: '<',
+            operator: "<".into(),
             lhs: Expr {
                 kind: Binary {
-                    operator: '+',
-                    lhs: Expr { kind: Number(3.0) }.into(),
+                    operator: "+".into(),
+                    lhs: Expr {
+                        kind: Number(3.0),
+                        span: Default::default(),
+                    }
+                    .into(),
                     rhs: Expr {
                         kind: Binary {
-                            operator: '-',
-                            lhs: Expr { kind: Number(2.0) }.into(),
+                            operator: "-".into(),
+                            lhs: Expr {
+                                kind: Number(2.0),
+                                span: Default::default(),
+                            }
+                            .into(),
                             rhs: Expr {
                                 kind: Binary {
-                                    operator: '*',
-                                    lhs: Expr { kind: Number(4.0) }.into(),
-                                    rhs: Expr { kind: Number(7.0) }.into(),
+                                    operator: "*".into(),
+                                    lhs: Expr {
+                                        kind: Number(4.0),
+                                        span: Default::default(),
+                                    }
+                                    .into(),
+                                    rhs: Expr {
+                                        kind: Number(7.0),
+                                        span: Default::default(),
+                                    }
+                                    .into(),
                                 },
+                                span: Default::default(),
                             }
                             .into(),
                         },
+                        span: Default::default(),
                     }
                     .into(),
                 },
+                span: Default::default(),
+            }
+            .into(),
+            rhs: Expr {
+                kind: Number(3.0),
+                span: Default::default(),
             }
             .into(),
-            rhs: Expr { kind: Number(3.0) }.into(),
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -252,28 +361,46 @@ fn test_parse_multiple_add_ops() {
     let (mut parser, mut lexer) = setup_parser_lexer!("1+2+3+4");
 
     let result = parser.parse_expression(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Binary {
-            operator: '+',
+            operator: "+".into(),
             lhs: Expr {
                 kind: Binary {
-                    operator: '+',
+                    operator: "+".into(),
                     lhs: Expr {
                         kind: Binary {
-                            operator: '+',
-                            lhs: Expr { kind: Number(1.0) }.into(),
-                            rhs: Expr { kind: Number(2.0) }.into(),
+                            operator: "+".into(),
+                            lhs: Expr {
+                                kind: Number(1.0),
+                                span: Default::default(),
+                            }
+                            .into(),
+                            rhs: Expr {
+                                kind: Number(2.0),
+                                span: Default::default(),
+                            }
+                            .into(),
                         },
+                        span: Default::default(),
+                    }
+                    .into(),
+                    rhs: Expr {
+                        kind: Number(3.0),
+                        span: Default::default(),
                     }
                     .into(),
-                    rhs: Expr { kind: Number(3.0) }.into(),
                 },
+                span: Default::default(),
+            }
+            .into(),
+            rhs: Expr {
+                kind: Number(4.0),
+                span: Default::default(),
             }
             .into(),
-            rhs: Expr { kind: Number(4.0) }.into(),
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -283,7 +410,7 @@ fn test_parse_function_proto_legal_basic() {
     let (mut parser, mut lexer) = setup_parser_lexer!("fn(three, four, five)");
 
     let result = parser.parse_function_prototype(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Prototype {
             name: "fn".to_owned(),
             args: vec![
@@ -292,8 +419,8 @@ fn test_parse_function_proto_legal_basic() {
                 String::from_str("five").unwrap(),
             ],
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -303,13 +430,13 @@ fn test_parse_function_proto_legal_no_args() {
     let (mut parser, mut lexer) = setup_parser_lexer!("fn()");
 
     let result = parser.parse_function_prototype(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Prototype {
             name: "fn".to_owned(),
             args: vec![],
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -319,13 +446,13 @@ fn test_parse_function_proto_legal_trailing_comma_after_args() {
     let (mut parser, mut lexer) = setup_parser_lexer!("fn(seven,)");
 
     let result = parser.parse_function_prototype(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Prototype {
             name: "fn".to_owned(),
             args: vec![String::from_str("seven").unwrap()],
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -335,9 +462,7 @@ fn test_parse_function_proto_illegal_no_fn_name() {
     let (mut parser, mut lexer) = setup_parser_lexer!("(three, four, five)");
 
     let result = parser.parse_function_prototype(&mut lexer);
-    let expected_result = None;
-
-    assert_eq!(result, expected_result);
+    assert!(result.is_err());
 }
 
 #[test]
@@ -345,8 +470,7 @@ fn test_parse_function_proto_illegal_no_opening_brace() {
     let (mut parser, mut lexer) = setup_parser_lexer!("fUNKthree, four, five)");
 
     let result = parser.parse_function_prototype(&mut lexer);
-    let expected_result = None;
-    assert_eq!(result, expected_result);
+    assert!(result.is_err());
 }
 
 #[test]
@@ -354,9 +478,7 @@ fn test_parse_function_proto_illegal_no_closing_brace() {
     let (mut parser, mut lexer) = setup_parser_lexer!("FuN(three, four, five");
 
     let result = parser.parse_function_prototype(&mut lexer);
-    let expected_result = None;
-
-    assert_eq!(result, expected_result);
+    assert!(result.is_err());
 }
 
 #[test]
@@ -364,42 +486,48 @@ fn test_parse_function_def() {
     let (mut parser, mut lexer) = setup_parser_lexer!("def fun(x, y, z)\n  x + y+z");
 
     let result = parser.parse_function_definition(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Function {
             prototype: Expr {
                 kind: Prototype {
                     name: "fun".into(),
                     args: vec!["x".into(), "y".into(), "z".into()],
                 },
+                span: Default::default(),
             }
             .into(),
             body: Expr {
                 kind: Binary {
-                    operator: '+',
+                    operator: "+".into(),
                     lhs: Expr {
                         kind: Binary {
-                            operator: '+',
+                            operator: "+".into(),
                             lhs: Expr {
                                 kind: Variable { name: "x".into() },
+                                span: Default::default(),
                             }
                             .into(),
                             rhs: Expr {
                                 kind: Variable { name: "y".into() },
+                                span: Default::default(),
                             }
                             .into(),
                         },
+                        span: Default::default(),
                     }
                     .into(),
                     rhs: Expr {
                         kind: Variable { name: "z".into() },
+                        span: Default::default(),
                     }
                     .into(),
                 },
+                span: Default::default(),
             }
             .into(),
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -409,13 +537,13 @@ fn test_parse_extern_legal_basic() {
     let (mut parser, mut lexer) = setup_parser_lexer!("extern sin(x)");
 
     let result = parser.parse_extern(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Prototype {
             name: "sin".to_owned(),
             args: vec!["x".into()],
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -425,32 +553,44 @@ fn test_parse_top_level_expr_legal_basic() {
     let (mut parser, mut lexer) = setup_parser_lexer!("5 + func(30.0)");
 
     let result = parser.parse_top_level_expression(&mut lexer);
-    let expected_result = Expr {
+    let expected_result = Ok(Expr {
         kind: Function {
             prototype: Expr {
                 kind: Prototype {
                     name: "__anon".to_owned(),
                     args: vec![],
                 },
+                span: Default::default(),
             }
             .into(),
             body: Expr {
                 kind: Binary {
-                    operator: '+',
-                    lhs: Expr { kind: Number(5.0) }.into(),
+                    operator: "+".into(),
+                    lhs: Expr {
+                        kind: Number(5.0),
+                        span: Default::default(),
+                    }
+                    .into(),
                     rhs: Expr {
                         kind: Call {
                             callee: "func".to_owned(),
-                            args: { vec![Expr { kind: Number(30.0) }] },
+                            args: {
+                                vec![Expr {
+                                    kind: Number(30.0),
+                                    span: Default::default(),
+                                }]
+                            },
                         },
+                        span: Default::default(),
                     }
                     .into(),
                 },
+                span: Default::default(),
             }
             .into(),
         },
-    }
-    .into();
+        span: Default::default(),
+    });
 
     assert_eq!(result, expected_result);
 }
@@ -464,12 +604,583 @@ fn test_parse_if_then_else() {
         kind: If(IfVal {
             if_boolish_test: Expr {
                 kind: Variable { name: "x".into() },
+                span: Default::default(),
+            }
+            .into(),
+            then: Expr {
+                kind: Number(1.0),
+                span: Default::default(),
+            }
+            .into(),
+            elves: Expr {
+                kind: Number(2.0),
+                span: Default::default(),
+            }
+            .into(),
+        }),
+        span: Default::default(),
+    };
+
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn test_parse_for_in() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("for i = 1, i < n, 2 in putchard(42)");
+
+    let result = parser.parse_for_in(&mut lexer).unwrap();
+    let expected_result = Expr {
+        kind: For(ForVal {
+            var_name: "i".into(),
+            start: Expr {
+                kind: Number(1.0),
+                span: Default::default(),
+            }
+            .into(),
+            end: Expr {
+                kind: Binary {
+                    operator: "<".into(),
+                    lhs: Expr {
+                        kind: Variable { name: "i".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                    rhs: Expr {
+                        kind: Variable { name: "n".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
+            }
+            .into(),
+            step: Some(
+                Expr {
+                    kind: Number(2.0),
+                    span: Default::default(),
+                }
+                .into(),
+            ),
+            body: Expr {
+                kind: Call {
+                    callee: "putchard".into(),
+                    args: vec![Expr {
+                        kind: Number(42.0),
+                        span: Default::default(),
+                    }],
+                },
+                span: Default::default(),
+            }
+            .into(),
+        }),
+        span: Default::default(),
+    };
+
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn test_parse_for_in_without_step() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("for i = 0, i < 3 in i");
+
+    let result = parser.parse_for_in(&mut lexer).unwrap();
+    match result.kind {
+        For(ForVal { step: None, .. }) => (),
+        _ => assert!(false, "Expected a For without a step, got {:#?}", result),
+    }
+}
+
+#[test]
+fn test_parse_while_do() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("while x do if x then break else continue");
+
+    let result = parser.parse_while_do(&mut lexer).unwrap();
+    let expected_result = Expr {
+        kind: While(WhileVal {
+            condition: Expr {
+                kind: Variable { name: "x".into() },
+                span: Default::default(),
+            }
+            .into(),
+            body: Expr {
+                kind: If(IfVal {
+                    if_boolish_test: Expr {
+                        kind: Variable { name: "x".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                    then: Expr {
+                        kind: Break,
+                        span: Default::default(),
+                    }
+                    .into(),
+                    elves: Expr {
+                        kind: Continue,
+                        span: Default::default(),
+                    }
+                    .into(),
+                }),
+                span: Default::default(),
+            }
+            .into(),
+        }),
+        span: Default::default(),
+    };
+
+    assert_eq!(result, expected_result);
+    // The span runs from `while` to the end of the body
+    assert_eq!(result.span.start.column, 1);
+    assert_eq!(result.span.end.column, 41);
+}
+
+#[test]
+fn test_parse_while_without_do() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("while x break");
+
+    let result = parser.parse_while_do(&mut lexer).unwrap_err();
+    assert_eq!(result.code, code::UNEXPECTED_TOKEN);
+    assert_eq!(result.message, "Expected 'do' after while, got 'break'");
+}
+
+#[test]
+fn test_parse_block() {
+    let number = |n| Expr {
+        kind: Number(n),
+        span: Default::default(),
+    };
+    let cases = [
+        ("{ 1 }", vec![number(1.0)]),
+        ("{ 1; 2 }", vec![number(1.0), number(2.0)]),
+        // A trailing ';' doesn't add anything
+        ("{ 1; 2; }", vec![number(1.0), number(2.0)]),
+        (
+            "{ 1; { 2 } }",
+            vec![
+                number(1.0),
+                Expr {
+                    kind: Block(vec![number(2.0)]),
+                    span: Default::default(),
+                },
+            ],
+        ),
+    ];
+
+    for (source, expected) in cases {
+        let mut parser = Parser::new();
+        let mut lexer = Lexer::new(source.as_bytes());
+        lexer.get_next_token();
+        let result = parser.parse_expression(&mut lexer).unwrap();
+        assert_eq!(result.kind, Block(expected), "{}", source);
+        assert_eq!(
+            result.span.end.column as usize,
+            source.len() + 1,
+            "{}",
+            source
+        );
+        assert_eq!(lexer.current_token(), &Some(Token::EOF), "{}", source);
+    }
+}
+
+#[test]
+fn test_parse_block_errors_leave_it_open() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("{ 1; { 2 3 } }");
+
+    let result = parser.parse_expression(&mut lexer).unwrap_err();
+    assert_eq!(result.message, "Expected ';' or '}' in block, got number 3");
+    assert_eq!(parser.take_open_blocks(), 2);
+    assert_eq!(parser.take_open_blocks(), 0);
+
+    let (mut parser, mut lexer) = setup_parser_lexer!("{ }");
+    let result = parser.parse_expression(&mut lexer).unwrap_err();
+    assert_eq!(result.message, "Expected an expression, got '}'");
+}
+
+#[test]
+fn test_parse_block_errors_dont_carry_over_to_the_next_item() {
+    let items: [(
+        &str,
+        fn(&mut Parser, &mut Lexer<&[u8]>) -> Result<Expr, Diagnostic>,
+    ); 3] = [
+        ("{ 2 3 }", |parser, lexer| {
+            parser.parse_top_level_expression(lexer)
+        }),
+        ("def f(x) { x 1 }", |parser, lexer| {
+            parser.parse_function_definition(lexer)
+        }),
+        ("extern g(x", |parser, lexer| parser.parse_extern(lexer)),
+    ];
+
+    for ((source, parse), expected) in items.into_iter().zip([1, 1, 0]) {
+        // Nothing recovers from this one, like `:ast { 1 +` in the REPL
+        let (mut parser, mut lexer) = setup_parser_lexer!("{ 1 +");
+        parser.parse_expression(&mut lexer).unwrap_err();
+
+        let mut lexer = Lexer::new(source.as_bytes());
+        lexer.get_next_token();
+        parse(&mut parser, &mut lexer).unwrap_err();
+        assert_eq!(parser.take_open_blocks(), expected, "{}", source);
+    }
+}
+
+#[test]
+fn test_parse_function_proto_args_without_commas() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("fn(three four)");
+
+    let result = parser.parse_function_prototype(&mut lexer);
+    let expected_result = Ok(Expr {
+        kind: Prototype {
+            name: "fn".to_owned(),
+            args: vec!["three".into(), "four".into()],
+        },
+        span: Default::default(),
+    });
+
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn test_parse_binary_operator_definition_registers_precedence() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def binary| 5 (a b) a + b");
+
+    let result = parser.parse_function_definition(&mut lexer).unwrap();
+    match result.kind {
+        Function { prototype, .. } => assert_eq!(
+            prototype.kind,
+            Prototype {
+                name: "binary|".into(),
+                args: vec!["a".into(), "b".into()],
+            }
+        ),
+        _ => assert!(false, "Expected a Function, got {:#?}", result),
+    }
+    assert_eq!(parser.environment.get_operator_precedence("|"), 5.into());
+}
+
+#[test]
+fn test_parse_binary_operator_definition_default_precedence() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def binary& (a b) a * b");
+
+    assert!(parser.parse_function_definition(&mut lexer).is_ok());
+    assert_eq!(parser.environment.get_operator_precedence("&"), 30.into());
+}
+
+#[test]
+fn test_parse_binary_operator_definition_wrong_arity() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def binary| 5 (a) a");
+
+    assert!(parser.parse_function_definition(&mut lexer).is_err());
+    assert_eq!(parser.environment.get_operator_precedence("|"), None);
+}
+
+#[test]
+fn test_parse_binary_operator_definition_warns_about_builtins() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def binary+ 50 (a b) a");
+
+    assert!(parser.parse_function_definition(&mut lexer).is_ok());
+
+    let warnings = parser.take_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].severity, Severity::Warning);
+    assert_eq!(warnings[0].code, code::SHADOWED_BUILTIN_OPERATOR);
+    // Warnings are only handed out once
+    assert!(parser.take_warnings().is_empty());
+}
+
+#[test]
+fn test_new_sets_up_comparison_precedences() {
+    let parser = Parser::new();
+
+    for op in ["<", ">", "<=", ">="] {
+        assert_eq!(parser.environment.get_operator_precedence(op), 10.into());
+    }
+    // Equality binds more loosely, so `a < b == c < d` compares the comparisons
+    for op in ["==", "!="] {
+        assert_eq!(parser.environment.get_operator_precedence(op), 9.into());
+    }
+}
+
+#[test]
+fn test_parse_comparison_operators() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("a <= b == c > 1");
+
+    let result = parser.parse_expression(&mut lexer).unwrap();
+
+    let variable = |name: &str| Expr {
+        kind: Variable { name: name.into() },
+        span: Default::default(),
+    };
+    let binary = |operator: &str, lhs, rhs| Expr {
+        kind: Binary {
+            operator: operator.into(),
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        span: Default::default(),
+    };
+    let expected_result = binary(
+        "==",
+        binary("<=", variable("a"), variable("b")),
+        binary(
+            ">",
+            variable("c"),
+            Expr {
+                kind: Number(1.0),
+                span: Default::default(),
+            },
+        ),
+    );
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn test_parse_multi_character_operator_definition() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def binary== 9 (a b) a");
+
+    let result = parser.parse_function_definition(&mut lexer).unwrap();
+    match result.kind {
+        Function { prototype, .. } => assert_eq!(
+            prototype.kind,
+            Prototype {
+                name: "binary==".into(),
+                args: vec!["a".into(), "b".into()],
+            }
+        ),
+        _ => assert!(false, "Expected a Function, got {:#?}", result),
+    }
+    assert_eq!(
+        parser.take_warnings()[0].code,
+        code::SHADOWED_BUILTIN_OPERATOR
+    );
+}
+
+#[test]
+fn test_parse_error_points_at_unexpected_token() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("(1 + 2;");
+
+    let result = parser.parse_expression(&mut lexer).unwrap_err();
+
+    assert_eq!(result.severity, Severity::Error);
+    assert_eq!(result.code, code::UNEXPECTED_TOKEN);
+    assert_eq!(result.message, "Expected ')', got ';'");
+    assert_eq!(result.span.start.column, 7);
+    assert_eq!(result.span.end.column, 8);
+}
+
+#[test]
+fn test_parse_user_defined_binary_operator_use() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("1 | 2 + 3");
+    parser.environment.add_operator_precedence(("|", 5));
+
+    let result = parser.parse_expression(&mut lexer);
+    // '|' binds less tightly than '+', so this is 1 | (2 + 3)
+    let expected_result = Ok(Expr {
+        kind: Binary {
+            operator: "|".into(),
+            lhs: Expr {
+                kind: Number(1.0),
+                span: Default::default(),
+            }
+            .into(),
+            rhs: Expr {
+                kind: Binary {
+                    operator: "+".into(),
+                    lhs: Expr {
+                        kind: Number(2.0),
+                        span: Default::default(),
+                    }
+                    .into(),
+                    rhs: Expr {
+                        kind: Number(3.0),
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
+            }
+            .into(),
+        },
+        span: Default::default(),
+    });
+
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn test_parse_unary() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("!-x");
+
+    let result = parser.parse_unary(&mut lexer);
+    let expected_result = Ok(Expr {
+        kind: Unary {
+            operator: "!".into(),
+            operand: Expr {
+                kind: Unary {
+                    operator: "-".into(),
+                    operand: Expr {
+                        kind: Variable { name: "x".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
+            }
+            .into(),
+        },
+        span: Default::default(),
+    });
+
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn test_parse_unary_in_binary_rhs() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("1 - -2");
+
+    let result = parser.parse_expression(&mut lexer);
+    let expected_result = Ok(Expr {
+        kind: Binary {
+            operator: "-".into(),
+            lhs: Expr {
+                kind: Number(1.0),
+                span: Default::default(),
+            }
+            .into(),
+            rhs: Expr {
+                kind: Unary {
+                    operator: "-".into(),
+                    operand: Expr {
+                        kind: Number(2.0),
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
+            }
+            .into(),
+        },
+        span: Default::default(),
+    });
+
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn test_parse_unary_operator_definition() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def unary! (v) if v then 0 else 1");
+
+    let result = parser.parse_function_definition(&mut lexer).unwrap();
+    match result.kind {
+        Function { prototype, .. } => assert_eq!(
+            prototype.kind,
+            Prototype {
+                name: "unary!".into(),
+                args: vec!["v".into()],
+            }
+        ),
+        _ => assert!(false, "Expected a Function, got {:#?}", result),
+    }
+}
+
+#[test]
+fn test_parse_unary_operator_definition_wrong_arity() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def unary! (a b) a");
+
+    assert!(parser.parse_function_definition(&mut lexer).is_err());
+}
+
+#[test]
+fn test_parse_var_in() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("var a = 1, b in a = b");
+
+    let result = parser.parse_var_in(&mut lexer).unwrap();
+    let expected_result = Expr {
+        kind: Var(VarVal {
+            bindings: vec![
+                (
+                    "a".into(),
+                    Some(
+                        Expr {
+                            kind: Number(1.0),
+                            span: Default::default(),
+                        }
+                        .into(),
+                    ),
+                ),
+                ("b".into(), None),
+            ],
+            body: Expr {
+                kind: Binary {
+                    operator: "=".into(),
+                    lhs: Expr {
+                        kind: Variable { name: "a".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                    rhs: Expr {
+                        kind: Variable { name: "b".into() },
+                        span: Default::default(),
+                    }
+                    .into(),
+                },
+                span: Default::default(),
             }
             .into(),
-            then: Expr { kind: Number(1.0) }.into(),
-            elves: Expr { kind: Number(2.0) }.into(),
         }),
+        span: Default::default(),
     };
 
     assert_eq!(result, expected_result);
 }
+
+#[test]
+fn test_parse_var_in_missing_in() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("var a = 1 a");
+
+    assert!(parser.parse_var_in(&mut lexer).is_err());
+}
+
+#[test]
+fn test_parse_records_spans() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("def f(x)\n  x *\n    y");
+
+    let span = |start: (usize, u32, u32), end: (usize, u32, u32)| Span {
+        start: SourceLocation {
+            offset: start.0,
+            line: start.1,
+            column: start.2,
+        },
+        end: SourceLocation {
+            offset: end.0,
+            line: end.1,
+            column: end.2,
+        },
+    };
+
+    let result = parser.parse_function_definition(&mut lexer).unwrap();
+    assert_eq!(result.span, span((0, 1, 1), (20, 3, 6)));
+
+    match result.kind {
+        Function { prototype, body } => {
+            assert_eq!(prototype.span, span((4, 1, 5), (8, 1, 9)));
+            // Binary expressions cover both of their operands
+            assert_eq!(body.span, span((11, 2, 3), (20, 3, 6)));
+            match body.kind {
+                Binary { lhs, rhs, .. } => {
+                    assert_eq!(lhs.span, span((11, 2, 3), (12, 2, 4)));
+                    assert_eq!(rhs.span, span((19, 3, 5), (20, 3, 6)));
+                }
+                _ => assert!(false, "Expected a Binary body"),
+            }
+        }
+        _ => assert!(false, "Expected a Function, got {:#?}", result),
+    }
+}
+
+#[test]
+fn test_parse_call_span_includes_closing_paren() {
+    let (mut parser, mut lexer) = setup_parser_lexer!("foo(1, 2) + 3");
+
+    let result = parser.parse_primary_expr(&mut lexer).unwrap();
+    assert_eq!(result.span.start.offset, 0);
+    assert_eq!(result.span.end.offset, 9);
+}
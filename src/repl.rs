@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use rustyline::{error::ReadlineError, Editor};
+
+use crate::driver::Driver;
+
+const PROMPT: &str = "ready> ";
+const CONTINUATION_PROMPT: &str = "  ...> ";
+const HISTORY_FILE_NAME: &str = ".kaleidoscope_history";
+
+/// Line edited interactive mode, entries are only handed to the driver once they're complete
+pub struct Repl<'d, 'ctx> {
+    driver: &'d mut Driver<'ctx>,
+    editor: Editor<()>,
+    history_path: Option<PathBuf>,
+}
+
+impl<'d, 'ctx> Repl<'d, 'ctx> {
+    pub fn new(driver: &'d mut Driver<'ctx>) -> Result<Self, std::io::Error> {
+        let editor =
+            Editor::<()>::new().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let history_path =
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME));
+
+        let mut repl = Repl {
+            driver,
+            editor,
+            history_path,
+        };
+        if let Some(path) = &repl.history_path {
+            // There won't be any history the first time around
+            repl.editor.load_history(path).ok();
+        }
+        Ok(repl)
+    }
+
+    pub fn run(&mut self) -> Result<(), std::io::Error> {
+        let mut entry = String::new();
+
+        loop {
+            let prompt = if entry.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+
+            match self.editor.readline(prompt) {
+                // Blank lines between entries shouldn't start a continuation
+                Ok(line) if entry.is_empty() && line.trim().is_empty() => (),
+                // Meta-commands always fit on one line
+                Ok(line) if entry.is_empty() && line.trim_start().starts_with(':') => {
+                    self.editor.add_history_entry(line.trim());
+                    self.driver.handle_command(&line)?;
+                }
+                Ok(line) => {
+                    entry.push_str(&line);
+                    entry.push('\n');
+                    if !is_complete(&entry) {
+                        continue;
+                    }
+
+                    self.editor.add_history_entry(entry.trim_end());
+                    self.driver.run_entry(&entry)?;
+                    entry.clear();
+                }
+                // Ctrl-C throws away whatever has been typed so far
+                Err(ReadlineError::Interrupted) => entry.clear(),
+                // Ctrl-D
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+            }
+        }
+
+        if let Some(path) = &self.history_path {
+            if let Err(e) = self.editor.save_history(path) {
+                eprintln!("Failed to save history to {}: {}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+// An entry is complete once its parentheses and braces are balanced and it ends with a ';'.
+// Comments run to the end of the line and don't count.
+fn is_complete(entry: &str) -> bool {
+    let mut depth = 0;
+    let mut last = None;
+
+    for line in entry.lines() {
+        let code = match line.find('#') {
+            Some(comment_start) => &line[..comment_start],
+            None => line,
+        };
+        for c in code.chars() {
+            match c {
+                '(' | '{' => depth += 1,
+                ')' | '}' => depth -= 1,
+                _ => (),
+            }
+            if !c.is_ascii_whitespace() {
+                last = Some(c);
+            }
+        }
+    }
+
+    // Too many closing brackets will never balance, so let the parser complain about it
+    depth < 0 || (depth == 0 && last == Some(';'))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,37 @@
+use super::*;
+
+#[test]
+fn test_is_complete_needs_semicolon() {
+    assert!(!is_complete("1 + 2\n"));
+    assert!(is_complete("1 + 2;\n"));
+    assert!(is_complete("1 + 2;   \n"));
+}
+
+#[test]
+fn test_is_complete_needs_balanced_parens() {
+    assert!(!is_complete("def foo(x)\n  (x + 1;\n"));
+    assert!(is_complete("def foo(x)\n  (x + 1);\n"));
+}
+
+#[test]
+fn test_is_complete_needs_balanced_braces() {
+    assert!(!is_complete("def foo(x) {\n  printd(x);\n"));
+    assert!(is_complete("def foo(x) {\n  printd(x);\n  x\n};\n"));
+}
+
+#[test]
+fn test_is_complete_gives_up_on_extra_closing_parens() {
+    assert!(is_complete("1 + 2)\n"));
+}
+
+#[test]
+fn test_is_complete_ignores_comments() {
+    assert!(!is_complete("1 + 2 # done;\n"));
+    assert!(!is_complete("foo( # (\n"));
+    assert!(is_complete("foo(1); # trailing comment\n"));
+}
+
+#[test]
+fn test_is_complete_empty_entry() {
+    assert!(!is_complete("\n"));
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+
+use crate::{
+    context::Context,
+    diagnostic::Diagnostic,
+    driver::{Drive, Driver, DriverOptions},
+};
+
+/// Runs Kaleidoscope from Rust. Like the REPL, everything defined stays around for later calls
+pub struct Session<'ctx> {
+    driver: Driver<'ctx>,
+    // Host and --load libraries are loaded before anything first runs
+    libraries_loaded: bool,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    /// The first mistake found in the source, any others are left out
+    Diagnostic(Diagnostic),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Diagnostic(diagnostic) => write!(
+                f,
+                "{}[{}]: {}",
+                diagnostic.severity, diagnostic.code, diagnostic.message
+            ),
+            SessionError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<Diagnostic> for SessionError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        SessionError::Diagnostic(diagnostic)
+    }
+}
+
+impl From<std::io::Error> for SessionError {
+    fn from(e: std::io::Error) -> Self {
+        SessionError::Io(e)
+    }
+}
+
+impl<'ctx> Session<'ctx> {
+    pub fn new(context: &'ctx Context) -> Self {
+        let driver = Driver::new(
+            Box::new(std::io::empty()),
+            Box::new(std::io::sink()),
+            context,
+        )
+        .with_error_output(Box::new(std::io::sink()));
+        Session {
+            driver,
+            libraries_loaded: false,
+        }
+    }
+
+    /// Picks the backend, libraries to load and so on, the same way the command line does
+    pub fn with_options(self, options: DriverOptions) -> Self {
+        Session {
+            driver: self.driver.with_options(options),
+            ..self
+        }
+    }
+
+    /// Runs every item in `source`, returning what its last top level expression evaluated to
+    pub fn eval_str(&mut self, source: &str) -> Result<Option<f64>, SessionError> {
+        self.run(source, false)
+    }
+
+    /// Adds the functions and externs in `source`, which can't have top level expressions
+    pub fn define(&mut self, source: &str) -> Result<(), SessionError> {
+        self.run(source, true).map(|_| ())
+    }
+
+    /// Calls a function defined earlier in the session
+    pub fn call_function(&mut self, name: &str, args: &[f64]) -> Result<f64, SessionError> {
+        Ok(self.driver.call_function(name, args)?)
+    }
+
+    fn run(&mut self, source: &str, definitions_only: bool) -> Result<Option<f64>, SessionError> {
+        if !self.libraries_loaded {
+            self.driver.load_libraries()?;
+            self.libraries_loaded = true;
+        }
+
+        let errors_before = self.driver.error_count();
+        self.driver.definitions_only = definitions_only;
+        let result = self.driver.run_entry(source);
+        self.driver.definitions_only = false;
+        result?;
+
+        match self.driver.errors().get(errors_before) {
+            Some(diagnostic) => Err(diagnostic.clone().into()),
+            None => Ok(self.driver.last_value()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,88 @@
+use super::*;
+use crate::{diagnostic::code, driver::Backend};
+use pretty_assertions::assert_eq;
+
+fn error_code(result: Result<impl fmt::Debug, SessionError>) -> &'static str {
+    match result {
+        Err(SessionError::Diagnostic(diagnostic)) => diagnostic.code,
+        other => panic!("Expected a diagnostic, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_eval_str_returns_last_value() {
+    let context = Context::create();
+    let mut session = Session::new(&context);
+
+    let value = session.eval_str("def square(x) x * x; square(2); square(3) + 1;");
+    assert_eq!(value.unwrap(), Some(10.0));
+    assert_eq!(
+        session.eval_str("def cube(x) x * square(x);").unwrap(),
+        None
+    );
+    assert_eq!(session.eval_str("cube(2);").unwrap(), Some(8.0));
+}
+
+#[test]
+fn test_define_then_call_function() {
+    let context = Context::create();
+    let mut session = Session::new(&context);
+
+    session
+        .define("def add(a b) a + b; def twice(x) add(x, x);")
+        .unwrap();
+
+    assert_eq!(session.call_function("add", &[1.0, 2.0]).unwrap(), 3.0);
+    assert_eq!(session.call_function("twice", &[4.0]).unwrap(), 8.0);
+}
+
+#[test]
+fn test_define_rejects_top_level_expressions() {
+    let context = Context::create();
+    let mut session = Session::new(&context);
+
+    let result = session.define("def one() 1; one();");
+
+    assert_eq!(error_code(result), code::UNEXPECTED_TOKEN);
+    assert_eq!(session.call_function("one", &[]).unwrap(), 1.0);
+}
+
+#[test]
+fn test_errors_are_returned() {
+    let context = Context::create();
+    let mut session = Session::new(&context);
+    session.define("def id(x) x;").unwrap();
+
+    assert_eq!(
+        error_code(session.eval_str("nope + 1;")),
+        code::UNKNOWN_VARIABLE
+    );
+    assert_eq!(
+        error_code(session.call_function("missing", &[])),
+        code::UNKNOWN_FUNCTION
+    );
+    assert_eq!(
+        error_code(session.call_function("id", &[1.0, 2.0])),
+        code::ARITY_MISMATCH
+    );
+    // Earlier mistakes don't count against later input
+    assert_eq!(session.eval_str("id(5);").unwrap(), Some(5.0));
+}
+
+#[test]
+fn test_interp_backend_session() {
+    let context = Context::create();
+    let mut session = Session::new(&context).with_options(DriverOptions {
+        backend: Backend::Interp,
+        ..Default::default()
+    });
+
+    session.define("def square(x) x * x;").unwrap();
+
+    assert_eq!(session.eval_str("square(3);").unwrap(), Some(9.0));
+    assert_eq!(session.call_function("square", &[5.0]).unwrap(), 25.0);
+    assert_eq!(
+        error_code(session.call_function("square", &[])),
+        code::ARITY_MISMATCH
+    );
+}
@@ -0,0 +1,115 @@
+//! Reads and builds syntax trees with nothing but the crate's public API
+
+use kaleidoscope::{
+    ast::{ExprKind, ForVal, IfVal, VarVal, WhileVal},
+    interpreter::Interpreter,
+    lexer::{Lex, Span},
+    parser::Parse,
+    Expr, Lexer, Parser,
+};
+use pretty_assertions::assert_eq;
+
+fn parse(source: &str) -> Expr {
+    let mut lexer = Lexer::new(source.as_bytes());
+    lexer.get_next_token();
+    Parser::new().parse_expression(&mut lexer).unwrap()
+}
+
+fn expr(kind: ExprKind) -> Box<Expr> {
+    Box::new(Expr {
+        kind,
+        span: Span::default(),
+    })
+}
+
+fn number(value: f64) -> Box<Expr> {
+    expr(ExprKind::Number(value))
+}
+
+fn variable(name: &str) -> Box<Expr> {
+    expr(ExprKind::Variable { name: name.into() })
+}
+
+fn binary(operator: &str, lhs: Box<Expr>, rhs: Box<Expr>) -> Box<Expr> {
+    expr(ExprKind::Binary {
+        operator: operator.into(),
+        lhs,
+        rhs,
+    })
+}
+
+#[test]
+fn test_control_flow_can_be_read() {
+    match parse("if x then 1 else 2").kind {
+        ExprKind::If(IfVal {
+            if_boolish_test,
+            then,
+            elves,
+        }) => {
+            assert_eq!(if_boolish_test, variable("x"));
+            assert_eq!((then, elves), (number(1.0), number(2.0)));
+        }
+        other => panic!("Expected an if, got {:?}", other),
+    }
+
+    match parse("for i = 0, i < 3, 2 in i").kind {
+        ExprKind::For(ForVal {
+            var_name,
+            start,
+            end,
+            step,
+            body,
+        }) => {
+            assert_eq!(var_name, "i");
+            assert_eq!(start, number(0.0));
+            assert_eq!(end, binary("<", variable("i"), number(3.0)));
+            assert_eq!(step, Some(number(2.0)));
+            assert_eq!(body, variable("i"));
+        }
+        other => panic!("Expected a for, got {:?}", other),
+    }
+
+    match parse("var a = 1, b in a").kind {
+        ExprKind::Var(VarVal { bindings, body }) => {
+            let expected = vec![
+                ("a".to_string(), Some(number(1.0))),
+                ("b".to_string(), None),
+            ];
+            assert_eq!(bindings, expected);
+            assert_eq!(body, variable("a"));
+        }
+        other => panic!("Expected a var, got {:?}", other),
+    }
+
+    match parse("while x do 1").kind {
+        ExprKind::While(WhileVal { condition, body }) => {
+            assert_eq!((condition, body), (variable("x"), number(1.0)));
+        }
+        other => panic!("Expected a while, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_control_flow_can_be_built() {
+    let count = binary("=", variable("n"), binary("+", variable("n"), number(1.0)));
+    let check = expr(ExprKind::If(IfVal {
+        if_boolish_test: binary("==", variable("n"), number(5.0)),
+        then: binary("*", variable("n"), number(2.0)),
+        elves: number(0.0),
+    }));
+    let built = expr(ExprKind::Var(VarVal {
+        bindings: vec![("n".into(), Some(number(0.0)))],
+        body: expr(ExprKind::Block(vec![
+            *expr(ExprKind::While(WhileVal {
+                condition: binary("<", variable("n"), number(5.0)),
+                body: count,
+            })),
+            *check,
+        ])),
+    }));
+
+    // Spans aren't compared, so a tree built by hand matches the parsed one
+    let parsed = parse("var n = 0 in { while n < 5 do n = n + 1; if n == 5 then n * 2 else 0 }");
+    assert_eq!(*built, parsed);
+    assert_eq!(Interpreter::new().evaluate(&built), Ok(10.0));
+}
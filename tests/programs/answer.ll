@@ -0,0 +1,14 @@
+; ModuleID = 'Kaleidoscope'
+source_filename = "Kaleidoscope"
+
+define double @one() {
+entry:
+  ret double 1.000000e+00
+}
+
+define double @answer() {
+entry:
+  %call_tmp = call double @one()
+  %addtmp = fadd double %call_tmp, 4.100000e+01
+  ret double %addtmp
+}